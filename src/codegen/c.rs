@@ -0,0 +1,372 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+
+//! lowering of `ir::Program` to a self-contained C file
+
+use std::fmt::Write as _;
+use crate::codegen::{CLOCK_INTERVAL, Escape, Move, StepCheck, escape};
+use crate::error::CompileError;
+use crate::interpreter::{BOUNDS_L_PANIC_MSG, OVERFLOW_PANIC_MSG, STEP_LIMIT_MSG, TIME_LIMIT_MSG, bounds_r_panic_msg};
+use crate::interpreter::{LIMIT_EXIT_CODE, PANIC_EXIT_CODE};
+use crate::ir::{Node, Op, Program};
+use crate::settings::{Eof, Settings, Tape, Terminal};
+use crate::token::Span;
+
+/// errors for settings the C backend cannot do
+pub fn check(settings: &Settings) -> Result<(), CompileError> {
+    if settings.target.is_some() {
+        return Err(CompileError::Unsupported("--target is not supported by the C backend, the C compiler picks the target".to_string()));
+    }
+    Ok(())
+}
+
+/// generates C source of whole program
+pub fn emit(program: &Program, source_name: &str, settings: &Settings) -> String {
+    let cells = settings.initial_cells(program);
+    let mut g = Gen {
+        f: String::new(),
+        settings,
+        source_name,
+        r_msg: bounds_r_panic_msg(settings.tape_limit().unwrap_or(cells)),
+        depth: 1,
+        reads: false,
+        steps: false,
+        panics: false,
+        limits: false,
+    };
+    g.block(&program.ops);
+    let Gen { f: body, reads, steps, panics, limits, .. } = g;
+
+    let mut f = String::new();
+    let bits = settings.cell_bits;
+    let mask = settings.cell_mask();
+    let timeout = settings.timeout_ms.is_some();
+    let terminal = settings.terminal != Terminal::Untouched;
+    let dump = settings.dump_on_panic || settings.dump_on_exit;
+
+    write!(f, "// {}\n", source_name.replace('\n', " "));
+    write!(f, "#include <stdint.h>\n#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\n");
+    if timeout {
+        write!(f, "#include <time.h>\n");
+    }
+    if terminal {
+        write!(f, "#if defined(__unix__) || defined(__APPLE__)\n#include <termios.h>\n#include <unistd.h>\n#define HAS_TERMIOS\n#endif\n");
+    }
+    write!(f, "\ntypedef uint{bits}_t cell;\n\n");
+    write!(f, "static cell *tape;\nstatic uint64_t len = {cells}u;\nstatic uint64_t pos = {};\n", program.start);
+    if panics || limits {
+        write!(f, "static const char *panic_msg;\nstatic const char *panic_at;\n");
+    }
+    if steps {
+        write!(f, "static uint64_t steps;\n");
+    }
+    if timeout {
+        write!(f, "static uint64_t deadline;\n");
+    }
+    if !program.tape.is_empty() {
+        let init: Vec<String> = program.tape.iter().map(|v| format!("{v}u")).collect();
+        write!(f, "static const cell tape_init[{}] = {{ {} }};\n", init.len(), init.join(", "));
+    }
+
+    if settings.tape == Tape::Growable {
+        write!(f, "
+// grows tape to at least `need` cells by doubling, new cells are zeroed, returns 0 when out of memory
+static int grow(uint64_t need) {{
+    uint64_t want = len * 2 > need ? len * 2 : need;
+");
+        if let Some(max) = settings.max_cells {
+            write!(f, "    if (want > {max}u) want = {max}u;\n");
+        }
+        write!(f, "    cell *t = realloc(tape, want * sizeof(cell));
+    if (!t) return 0;
+    memset(t + len, 0, (want - len) * sizeof(cell));
+    tape = t;
+    len = want;
+    return 1;
+}}
+");
+    }
+    if timeout {
+        write!(f, "
+// nanoseconds of monotonic clock
+static uint64_t now_ns(void) {{
+    struct timespec ts;
+    clock_gettime(CLOCK_MONOTONIC, &ts);
+    return (uint64_t)ts.tv_sec * 1000000000u + (uint64_t)ts.tv_nsec;
+}}
+");
+    }
+    if reads {
+        let eof = match settings.eof {
+            Eof::Zero => "    else tape[pos] = 0;\n".to_string(),
+            Eof::MinusOne => format!("    else tape[pos] = {mask}u;\n"),
+            Eof::Unchanged => String::new(),
+        };
+        let nl = if settings.override_new_line_to_null { "c == '\\n' ? 0 : (cell)c" } else { "(cell)c" };
+        write!(f, "
+// ',' with output flushed first
+static void input(void) {{
+    fflush(stdout);
+    int c = getchar();
+    if (c != EOF) tape[pos] = {nl};
+{eof}}}
+");
+    }
+    if dump {
+        write_dump(&mut f, settings);
+    }
+
+    write!(f, "
+static int code(void) {{
+    int exit_code = 0;
+    tape = calloc(len, sizeof(cell));
+    if (!tape) goto no_tape;
+");
+    if !program.tape.is_empty() {
+        write!(f, "    memcpy(tape, tape_init, sizeof tape_init);\n");
+    }
+    if !program.output.is_empty() {
+        write!(f, "    fwrite(\"{}\", 1, {}, stdout);\n", escape(&program.output, Escape::Octal), program.output.len());
+    }
+    if let Some(ms) = settings.timeout_ms {
+        write!(f, "    deadline = now_ns() + {}u;\n", ms.saturating_mul(1_000_000));
+    }
+    f += &body;
+    if settings.dump_on_exit {
+        write!(f, "    dump();\n");
+    }
+    write!(f, "    goto end;\n");
+    if panics {
+        write!(f, "panic:\n    exit_code = {PANIC_EXIT_CODE};\n    goto report;\n");
+    }
+    if limits {
+        write!(f, "limit:\n    exit_code = {LIMIT_EXIT_CODE};\n    goto report;\n");
+    }
+    if panics || limits {
+        write!(f, "report:\n");
+        if settings.dump_on_panic {
+            write!(f, "    dump();\n");
+        }
+        write!(f, "    printf(\"\\npanicked: %s | at %s\\n\", panic_msg, panic_at);\n    goto end;\n");
+    }
+    write!(f, "no_tape:
+    puts(\"failed to allocate tape\");
+    exit_code = 1;
+end:
+    free(tape);
+    return exit_code;
+}}
+");
+
+    write!(f, "\nint main(void) {{\n");
+    if terminal {
+        let change = match settings.terminal {
+            Terminal::Cooked => "set.c_lflag |= ICANON | ECHO;",
+            _ => "set.c_lflag &= ~(tcflag_t)(ICANON | ECHO);",
+        };
+        write!(f, "#ifdef HAS_TERMIOS
+    // piped input is left alone
+    struct termios old, set;
+    int tty = isatty(0) && tcgetattr(0, &old) == 0;
+    if (tty) {{
+        set = old;
+        {change}
+        tcsetattr(0, TCSANOW, &set);
+    }}
+#endif
+    int exit_code = code();
+#ifdef HAS_TERMIOS
+    if (tty) tcsetattr(0, TCSANOW, &old);
+#endif
+    return exit_code;
+}}
+");
+    } else {
+        write!(f, "    return code();\n}}\n");
+    }
+    f
+}
+
+/// `dump` prints data pointer and a window of `Settings::dump_width` cells around it to stderr,
+/// writes whole tape to `Settings::dump_file` as little endian
+fn write_dump(f: &mut String, settings: &Settings) {
+    let width = settings.dump_width;
+    let half = width / 2;
+    let digits = settings.cell_bits / 4;
+    let len = if settings.tape == Tape::Growable { "len".to_string() } else { format!("{}u", settings.cell_count) };
+    write!(f, "
+static void dump(void) {{
+    uint64_t start = pos >= {half}u ? pos - {half}u : 0;
+    uint64_t end = {len} - start > {width}u ? start + {width}u : {len};
+    fflush(stdout);
+    fprintf(stderr, \"\\ntape dump, pointer at %llu\\ncells %llu..%llu:\", (unsigned long long)pos, (unsigned long long)start, (unsigned long long)end);
+    for (uint64_t k = start; k < end; k++) {{
+        fprintf(stderr, k == pos ? \" [%0{digits}llx]\" : \" %0{digits}llx\", (unsigned long long)tape[k]);
+    }}
+    fprintf(stderr, \"\\n\");
+");
+    if let Some(path) = &settings.dump_file {
+        let path = escape(path.to_string_lossy().as_bytes(), Escape::Octal);
+        write!(f, "    FILE *file = fopen(\"{path}\", \"wb\");
+    if (!file) {{
+        fprintf(stderr, \"failed to write tape to %s\\n\", \"{path}\");
+        return;
+    }}
+    for (uint64_t k = 0; k < {len}; k++) {{
+        for (unsigned b = 0; b < sizeof(cell); b++) putc((int)(tape[k] >> (8 * b) & 0xff), file);
+    }}
+    fclose(file);
+");
+    }
+    write!(f, "}}\n");
+}
+
+struct Gen<'a> {
+    f: String,
+    settings: &'a Settings,
+    source_name: &'a str,
+    r_msg: String,
+    /// indentation
+    depth: usize,
+    /// `input` is used
+    reads: bool,
+    /// loop iterations are counted
+    steps: bool,
+    /// `panic` label is used
+    panics: bool,
+    /// `limit` label is used
+    limits: bool,
+}
+
+impl Gen<'_> {
+    fn line(&mut self, s: &str) {
+        write!(self.f, "{:1$}{s}\n", "", self.depth * 4);
+    }
+
+    /// C string with `file:line:col` of `span`
+    fn at(&self, span: Span) -> String {
+        escape(format!("{}:{}:{}", self.source_name, span.line, span.col).as_bytes(), Escape::Octal)
+    }
+
+    fn panic(&mut self, cond: &str, msg: &str, span: Span) {
+        self.panics = true;
+        let at = self.at(span);
+        self.line(&format!("if ({cond}) {{ panic_msg = \"{}\"; panic_at = \"{at}\"; goto panic; }}", escape(msg.as_bytes(), Escape::Octal)));
+    }
+
+    fn block(&mut self, ops: &[Node]) {
+        for node in ops {
+            self.process_op(node);
+        }
+    }
+
+    /// `var = pos + n`, bounds checked or wrapped, a growable tape grows to it
+    fn offset(&mut self, var: &str, n: isize, span: Span, in_bounds: bool) {
+        match Move::new(n, in_bounds, self.settings) {
+            Move::Wrap { k, back } => { self.line(&format!("{var} = pos >= {back}u ? pos - {back}u : pos + {k}u;")); }
+            Move::Right { m, limit, grow } => {
+                if let Some(limit) = limit {
+                    let msg = self.r_msg.clone();
+                    self.panic(&format!("{limit}u - pos <= {m}u"), &msg, span);
+                }
+                self.line(&format!("{var} = pos + {m}u;"));
+                if grow {
+                    self.line(&format!("if ({var} >= len && !grow({var} + 1)) goto no_tape;"));
+                }
+            }
+            Move::Left { m, check } => {
+                if check {
+                    self.panic(&format!("pos < {m}u"), BOUNDS_L_PANIC_MSG, span);
+                }
+                self.line(&format!("{var} = pos - {m}u;"));
+            }
+        }
+    }
+
+    /// `StepCheck` of a loop iteration, a limit jumps to `limit`
+    fn count_step(&mut self, span: Span) {
+        let Some(check) = StepCheck::new(self.settings) else { return; };
+        self.steps = true;
+        let at = self.at(span);
+        match check.max {
+            Some(max) => {
+                self.limits = true;
+                self.line(&format!("if (++steps > {max}u) {{ panic_msg = \"{STEP_LIMIT_MSG}\"; panic_at = \"{at}\"; goto limit; }}"));
+            }
+            None => { self.line("steps++;"); }
+        }
+        if check.clock {
+            self.limits = true;
+            self.line(&format!("if ((steps & {}u) == 0 && now_ns() > deadline) {{ panic_msg = \"{TIME_LIMIT_MSG}\"; panic_at = \"{at}\"; goto limit; }}", CLOCK_INTERVAL - 1));
+        }
+    }
+
+    fn process_op(&mut self, node: &Node) {
+        let Node { op, span, in_bounds } = node;
+        let span = *span;
+        let mask = self.settings.cell_mask();
+        match op {
+            &Op::Move(n) => { self.offset("pos", n, span, *in_bounds); }
+            &Op::Add(n) => {
+                let m = n.unsigned_abs() as u64;
+                if self.settings.check_overflow {
+                    let cond = match n {
+                        _ if m > mask => "1".to_string(),
+                        1.. => format!("tape[pos] > {}u", mask - m),
+                        _ => format!("tape[pos] < {m}u"),
+                    };
+                    self.panic(&cond, OVERFLOW_PANIC_MSG, span);
+                }
+                self.line(&format!("tape[pos] += {}u;", n as i64 as u64 & mask));
+            }
+            Op::Out => { self.line("putchar((unsigned char)tape[pos]);"); }
+            Op::In => {
+                self.reads = true;
+                self.line("input();");
+            }
+            Op::PrintNum => { self.line("printf(\"%llu\\n\", (unsigned long long)tape[pos]);"); }
+            Op::SetZero => { self.line("tape[pos] = 0;"); }
+            Op::Loop(body) => {
+                self.line("while (tape[pos]) {");
+                self.depth += 1;
+                self.count_step(span);
+                self.block(body);
+                self.depth -= 1;
+                self.line("}");
+            }
+            &Op::Scan(n) => {
+                self.line("while (tape[pos]) {");
+                self.depth += 1;
+                self.count_step(span);
+                self.offset("pos", n, span, false);
+                self.depth -= 1;
+                self.line("}");
+            }
+            &Op::MulAdd(offset, factor) => {
+                self.line("if (tape[pos]) {");
+                self.depth += 1;
+                self.line("uint64_t t;");
+                self.offset("t", offset, span, *in_bounds);
+                // wide multiplication, `cell` may be promoted to a signed int
+                self.line(&format!("tape[t] = (cell)(tape[t] + (uint64_t)tape[pos] * {}u);", factor as i64 as u64));
+                self.depth -= 1;
+                self.line("}");
+            }
+        }
+    }
+}
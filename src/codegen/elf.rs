@@ -0,0 +1,770 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+
+//! static x86-64 Linux executable written directly, without assembler or linker
+//!
+//! the assembly of `codegen::x86_64` is encoded by a small assembler which knows just the instructions it uses,
+//! every jump is `rel32` and every rip relative operand `disp32`
+
+use std::collections::HashMap;
+use crate::error::CompileError;
+use crate::ir::Program;
+use crate::settings::Settings;
+use super::x86_64;
+
+/// where the file is mapped
+const BASE: u64 = 0x400000;
+const PAGE: u64 = 0x1000;
+const HEADER_SIZE: u64 = 64;
+const PROGRAM_HEADER_SIZE: u64 = 56;
+const PROGRAM_HEADERS: u64 = 3;
+/// bss ends below the top of the user address space
+const MAX_BSS: u64 = (1 << 47) - (1 << 32);
+
+/// errors for settings the ELF backend cannot do
+pub fn check(settings: &Settings) -> Result<(), CompileError> {
+    x86_64::check(settings)
+}
+
+/// generates whole executable, errors when the assembly uses something the assembler does not know
+pub fn emit(program: &Program, source_name: &str, settings: &Settings) -> Result<Vec<u8>, CompileError> {
+    let asm = x86_64::emit(program, source_name, settings);
+    let mut a = Assembler::default();
+    for line in asm.lines() {
+        if a.line(line).is_none() {
+            return Err(CompileError::Unsupported(format!("the ELF backend cannot encode `{}`", line.trim())));
+        }
+    }
+    a.link()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Section {
+    #[default]
+    Text,
+    Rodata,
+    Bss,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    /// (number, bytes)
+    Reg(u8, u8),
+    Imm(i64),
+    Mem(Mem),
+    Label(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Mem {
+    /// bytes from `byte ptr` and such
+    size: Option<u8>,
+    base: Option<u8>,
+    /// (register, scale)
+    index: Option<(u8, u8)>,
+    /// rip relative address of this label
+    label: Option<String>,
+    disp: i64,
+}
+
+/// `rel32` or `disp32` at `at` in text, relative to `next` which is the end of its instruction
+struct Fixup {
+    at: usize,
+    next: usize,
+    label: String,
+    addend: i64,
+}
+
+#[derive(Default)]
+struct Assembler {
+    section: Section,
+    text: Vec<u8>,
+    rodata: Vec<u8>,
+    bss: u64,
+    labels: HashMap<String, (Section, u64)>,
+    fixups: Vec<Fixup>,
+}
+
+/// (number, bytes) of a general purpose register
+fn register(name: &str) -> Option<(u8, u8)> {
+    const R64: [&str; 16] = ["rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15"];
+    const R32: [&str; 8] = ["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi"];
+    const R16: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+    const R8: [&str; 8] = ["al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil"];
+    if let Some(n) = R64.iter().position(|r| *r == name) {
+        return Some((n as u8, 8));
+    }
+    for (names, size) in [(&R32, 4), (&R16, 2), (&R8, 1)] {
+        if let Some(n) = names.iter().position(|r| *r == name) {
+            return Some((n as u8, size));
+        }
+    }
+    // r8d, r8w, r8b
+    let (num, size) = match name.as_bytes().last()? {
+        b'd' => (&name[1..name.len() - 1], 4),
+        b'w' => (&name[1..name.len() - 1], 2),
+        b'b' => (&name[1..name.len() - 1], 1),
+        _ => { return None; }
+    };
+    let n: u8 = num.parse().ok().filter(|n| (8..16).contains(n))?;
+    name.starts_with('r').then_some((n, size))
+}
+
+fn number(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if let Some(c) = s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+        return c.bytes().next().map(i64::from);
+    }
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let v = match s.strip_prefix("0x") {
+        Some(h) => u64::from_str_radix(h, 16).ok()?,
+        None => s.parse::<u64>().ok()?,
+    } as i64;
+    Some(if neg { v.wrapping_neg() } else { v })
+}
+
+fn operand(s: &str) -> Option<Operand> {
+    let s = s.trim();
+    if let Some(r) = register(s) {
+        return Some(Operand::Reg(r.0, r.1));
+    }
+    if let Some(v) = number(s) {
+        return Some(Operand::Imm(v));
+    }
+    let (size, rest) = match s.split_once(" ptr ") {
+        Some((size, rest)) => (Some(match size { "byte" => 1, "word" => 2, "dword" => 4, _ => 8 }), rest.trim()),
+        None => (None, s),
+    };
+    let Some(inner) = rest.strip_prefix('[').and_then(|r| r.strip_suffix(']')) else {
+        return Some(Operand::Label(s.to_string()));
+    };
+    let mut m = Mem { size, ..Default::default() };
+    for term in inner.split('+').map(str::trim) {
+        if term == "rip" {
+            continue;
+        }
+        if let Some((r, scale)) = term.split_once('*') {
+            let r = register(r)?.0;
+            m.index = Some((r, scale.parse().ok().filter(|s: &u8| s.is_power_of_two() && *s <= 8)?));
+        } else if let Some((r, _)) = register(term) {
+            match m.base {
+                None => { m.base = Some(r); }
+                Some(_) => { m.index = Some((r, 1)); }
+            }
+        } else if let Some(v) = number(term) {
+            m.disp += v;
+        } else {
+            m.label = Some(term.to_string());
+        }
+    }
+    Some(Operand::Mem(m))
+}
+
+/// splits operands at commas outside of quotes
+fn operands(s: &str) -> Option<Vec<Operand>> {
+    let mut r = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '\'' => { quoted = !quoted; }
+            ',' if !quoted => {
+                r.push(operand(&s[start..i])?);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !s[start..].trim().is_empty() {
+        r.push(operand(&s[start..])?);
+    }
+    Some(r)
+}
+
+/// bytes of `.ascii` string
+fn unescape(s: &str) -> Option<Vec<u8>> {
+    let b = s.trim().strip_prefix('"')?.strip_suffix('"')?.as_bytes();
+    let mut r = vec![];
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] == b'\\' {
+            let next = *b.get(i + 1)?;
+            if next.is_ascii_digit() {
+                let o = std::str::from_utf8(b.get(i + 1..i + 4)?).ok()?;
+                r.push(u8::from_str_radix(o, 8).ok()?);
+                i += 4;
+            } else {
+                r.push(next);
+                i += 2;
+            }
+        } else {
+            r.push(b[i]);
+            i += 1;
+        }
+    }
+    Some(r)
+}
+
+/// condition code of `j{cc}`
+fn condition(cc: &str) -> Option<u8> {
+    Some(match cc {
+        "o" => 0x0, "no" => 0x1, "b" | "c" | "nae" => 0x2, "ae" | "nb" | "nc" => 0x3,
+        "e" | "z" => 0x4, "ne" | "nz" => 0x5, "be" | "na" => 0x6, "a" | "nbe" => 0x7,
+        "s" => 0x8, "ns" => 0x9, "l" | "nge" => 0xc, "ge" | "nl" => 0xd, "le" | "ng" => 0xe, "g" | "nle" => 0xf,
+        _ => { return None; }
+    })
+}
+
+impl Assembler {
+    fn here(&self) -> u64 {
+        match self.section {
+            Section::Text => self.text.len() as u64,
+            Section::Rodata => self.rodata.len() as u64,
+            Section::Bss => self.bss,
+        }
+    }
+
+    /// `None` when bss would not fit in the address space
+    fn data(&mut self, bytes: &[u8]) -> Option<()> {
+        match self.section {
+            Section::Text => { self.text.extend_from_slice(bytes); }
+            Section::Rodata => { self.rodata.extend_from_slice(bytes); }
+            Section::Bss => { self.bss = self.bss.checked_add(bytes.len() as u64)?; }
+        }
+        Some(())
+    }
+
+    /// `None` when bss would not fit in the address space
+    fn skip(&mut self, n: u64) -> Option<()> {
+        match self.section {
+            Section::Bss => { self.bss = self.bss.checked_add(n).filter(|b| *b <= MAX_BSS)?; }
+            // only alignment padding goes there
+            _ => { self.data(&vec![0; usize::try_from(n).ok().filter(|n| *n <= PAGE as usize)?])?; }
+        }
+        Some(())
+    }
+
+    /// `None` when the line is not understood
+    fn line(&mut self, line: &str) -> Option<()> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Some(());
+        }
+        if let Some(label) = line.strip_suffix(':') {
+            self.labels.insert(label.to_string(), (self.section, self.here()));
+            return Some(());
+        }
+        let (op, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match op {
+            ".intel_syntax" | ".globl" => {}
+            ".text" => { self.section = Section::Text; }
+            ".section" => {
+                self.section = match rest.trim() {
+                    ".rodata" => Section::Rodata,
+                    ".bss" => Section::Bss,
+                    _ => Section::Text,
+                };
+            }
+            ".ascii" => { self.data(&unescape(rest)?)?; }
+            ".byte" | ".short" | ".long" | ".quad" => {
+                let size = match op { ".byte" => 1, ".short" => 2, ".long" => 4, _ => 8 };
+                for v in rest.split(',') {
+                    let v = v.trim().parse::<u64>().ok()?;
+                    self.data(&v.to_le_bytes()[..size])?;
+                }
+            }
+            ".skip" => { self.skip(rest.trim().parse().ok()?)?; }
+            ".balign" => {
+                let align: u64 = rest.trim().parse().ok().filter(|a: &u64| a.is_power_of_two() && *a <= PAGE)?;
+                let pad = (align - self.here() % align) % align;
+                self.skip(pad)?;
+            }
+            _ => { self.instruction(op, rest)?; }
+        }
+        Some(())
+    }
+
+    /// prefixes, opcode and ModRM of an instruction with `reg` field and `rm` operand, `imm` follows them
+    fn encode(&mut self, size: u8, opcode: &[u8], reg: u8, rm: &Operand, imm: &[u8]) -> Option<()> {
+        let mut rex = if size == 8 { 0x48 } else { 0 };
+        if reg >= 8 {
+            rex |= 0x44;
+        }
+        let mut out = vec![];
+        if size == 2 {
+            out.push(0x66);
+        }
+        let mut body = vec![];
+        let mut fixup = None;
+        match rm {
+            Operand::Reg(r, s) => {
+                if *r >= 8 {
+                    rex |= 0x41;
+                }
+                // spl, bpl, sil and dil exist only with REX
+                if *s == 1 && (4..8).contains(r) {
+                    rex |= 0x40;
+                }
+                body.push(0xc0 | (reg & 7) << 3 | (r & 7));
+            }
+            Operand::Mem(m) if m.label.is_some() => {
+                body.push((reg & 7) << 3 | 0b101);
+                fixup = Some((body.len(), m.label.clone()?, m.disp));
+                body.extend_from_slice(&[0; 4]);
+            }
+            Operand::Mem(m) => {
+                let base = m.base?;
+                if base >= 8 {
+                    rex |= 0x41;
+                }
+                if let Some((index, _)) = m.index && index >= 8 {
+                    rex |= 0x42;
+                }
+                let (mode, disp): (u8, Vec<u8>) = match m.disp {
+                    // rbp and r13 as base always have displacement
+                    0 if base & 7 != 5 => (0b00, vec![]),
+                    d if i8::try_from(d).is_ok() => (0b01, vec![d as u8]),
+                    d => (0b10, i32::try_from(d).ok()?.to_le_bytes().to_vec()),
+                };
+                match m.index {
+                    Some((index, scale)) => {
+                        body.push(mode << 6 | (reg & 7) << 3 | 0b100);
+                        body.push((scale.trailing_zeros() as u8) << 6 | (index & 7) << 3 | (base & 7));
+                    }
+                    // rsp and r12 as base need SIB
+                    None if base & 7 == 4 => {
+                        body.push(mode << 6 | (reg & 7) << 3 | 0b100);
+                        body.push(0b00_100_000 | (base & 7));
+                    }
+                    None => { body.push(mode << 6 | (reg & 7) << 3 | (base & 7)); }
+                }
+                body.extend_from_slice(&disp);
+            }
+            _ => { return None; }
+        }
+        if rex != 0 {
+            out.push(rex);
+        }
+        out.extend_from_slice(opcode);
+        let start = self.text.len() + out.len();
+        out.extend_from_slice(&body);
+        out.extend_from_slice(imm);
+        self.text.extend_from_slice(&out);
+        if let Some((at, label, addend)) = fixup {
+            self.fixups.push(Fixup { at: start + at, next: self.text.len(), label, addend });
+        }
+        Some(())
+    }
+
+    /// `rel32` to `label` after `opcode`
+    fn branch(&mut self, opcode: &[u8], label: &str) {
+        self.text.extend_from_slice(opcode);
+        let at = self.text.len();
+        self.text.extend_from_slice(&[0; 4]);
+        self.fixups.push(Fixup { at, next: self.text.len(), label: label.to_string(), addend: 0 });
+    }
+
+    /// `None` for an instruction or operands it does not know
+    fn instruction(&mut self, op: &str, rest: &str) -> Option<()> {
+        use Operand::*;
+        let ops = operands(rest)?;
+        let size_of = |o: &Operand| match o {
+            Reg(_, s) => Some(*s),
+            Mem(m) => m.size,
+            _ => None,
+        };
+        // a 64-bit operand takes sign extended 32-bit immediate
+        let imm = |v: i64, size: u8| -> Option<Vec<u8>> {
+            Some(match size {
+                1 => vec![v as u8],
+                2 => (v as i16).to_le_bytes().to_vec(),
+                4 => (v as i32).to_le_bytes().to_vec(),
+                _ => i32::try_from(v).ok()?.to_le_bytes().to_vec(),
+            })
+        };
+        // add, or, and, sub, xor, cmp and their /digit
+        let alu = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"].iter().position(|a| *a == op).map(|n| n as u8);
+        match (op, &ops[..]) {
+            ("ret", []) => { self.text.push(0xc3); }
+            ("syscall", []) => { self.text.extend_from_slice(&[0x0f, 0x05]); }
+            ("rep", [Label(l)]) if l == "movsb" => { self.text.extend_from_slice(&[0xf3, 0xa4]); }
+            ("jmp", [Label(l)]) => { self.branch(&[0xe9], l); }
+            ("call", [Label(l)]) => { self.branch(&[0xe8], l); }
+            (_, [Label(l)]) if op.starts_with('j') && condition(&op[1..]).is_some() => {
+                let cc = condition(&op[1..])?;
+                self.branch(&[0x0f, 0x80 | cc], l);
+            }
+            ("push" | "pop", [Reg(r, 8)]) => {
+                if *r >= 8 {
+                    self.text.push(0x41);
+                }
+                self.text.push(if op == "push" { 0x50 } else { 0x58 } | (r & 7));
+            }
+            ("mov", [Reg(r, s), Imm(v)]) => {
+                match s {
+                    8 if i32::try_from(*v).is_ok() => { self.encode(8, &[0xc7], 0, &ops[0], &imm(*v, 4)?)?; }
+                    _ => {
+                        let mut out = vec![];
+                        if *s == 2 {
+                            out.push(0x66);
+                        }
+                        let rex = if *s == 8 { 0x48 } else { 0 } | if *r >= 8 { 0x41 } else { 0 };
+                        if rex != 0 {
+                            out.push(rex);
+                        }
+                        out.push(if *s == 1 { 0xb0 } else { 0xb8 } | (r & 7));
+                        out.extend_from_slice(&v.to_le_bytes()[..*s as usize]);
+                        self.text.extend_from_slice(&out);
+                    }
+                }
+            }
+            ("mov", [Mem(_), Imm(v)]) => {
+                let s = size_of(&ops[0])?;
+                self.encode(s, &[if s == 1 { 0xc6 } else { 0xc7 }], 0, &ops[0], &imm(*v, s)?)?;
+            }
+            ("mov", [Reg(..) | Mem(_), Reg(r, s)]) => { self.encode(*s, &[if *s == 1 { 0x88 } else { 0x89 }], *r, &ops[0], &[])?; }
+            ("mov", [Reg(r, s), Mem(_)]) => { self.encode(*s, &[if *s == 1 { 0x8a } else { 0x8b }], *r, &ops[1], &[])?; }
+            ("movzx", [Reg(r, s), Mem(m)]) => {
+                let from = if m.size == Some(1) { 0xb6 } else { 0xb7 };
+                self.encode(*s, &[0x0f, from], *r, &ops[1], &[])?;
+            }
+            ("lea", [Reg(r, s), Mem(_)]) => { self.encode(*s, &[0x8d], *r, &ops[1], &[])?; }
+            (_, [Reg(..) | Mem(_), Reg(r, s)]) if alu.is_some() => {
+                let n = alu?;
+                self.encode(*s, &[n * 8 + if *s == 1 { 0 } else { 1 }], *r, &ops[0], &[])?;
+            }
+            (_, [Reg(r, s), Mem(_)]) if alu.is_some() => {
+                let n = alu?;
+                self.encode(*s, &[n * 8 + if *s == 1 { 2 } else { 3 }], *r, &ops[1], &[])?;
+            }
+            (_, [Reg(..) | Mem(_), Imm(v)]) if alu.is_some() => {
+                let n = alu?;
+                let s = size_of(&ops[0])?;
+                match s {
+                    1 => { self.encode(1, &[0x80], n, &ops[0], &imm(*v, 1)?)?; }
+                    _ if i8::try_from(*v).is_ok() => { self.encode(s, &[0x83], n, &ops[0], &imm(*v, 1)?)?; }
+                    _ => { self.encode(s, &[0x81], n, &ops[0], &imm(*v, s)?)?; }
+                }
+            }
+            ("test", [Reg(..) | Mem(_), Reg(r, s)]) => { self.encode(*s, &[if *s == 1 { 0x84 } else { 0x85 }], *r, &ops[0], &[])?; }
+            ("test", [Reg(..) | Mem(_), Imm(v)]) => {
+                let s = size_of(&ops[0])?;
+                self.encode(s, &[if s == 1 { 0xf6 } else { 0xf7 }], 0, &ops[0], &imm(*v, s)?)?;
+            }
+            ("inc" | "dec" | "mul" | "div", [Reg(..) | Mem(_)]) => {
+                let s = size_of(&ops[0])?;
+                let (opcode, digit) = match op {
+                    "inc" => (0xfe, 0),
+                    "dec" => (0xfe, 1),
+                    "mul" => (0xf6, 4),
+                    _ => (0xf6, 6),
+                };
+                self.encode(s, &[opcode | if s == 1 { 0 } else { 1 }], digit, &ops[0], &[])?;
+            }
+            ("imul", [Reg(r, s), Reg(..) | Mem(_), Imm(v)]) => {
+                match i8::try_from(*v) {
+                    Ok(b) => { self.encode(*s, &[0x6b], *r, &ops[1], &[b as u8])?; }
+                    Err(_) => { self.encode(*s, &[0x69], *r, &ops[1], &imm(*v, 4)?)?; }
+                }
+            }
+            _ => { return None; }
+        }
+        Some(())
+    }
+
+    /// resolves labels and lays out the file: headers, text and rodata in one read only executable segment,
+    /// bss in a writable one
+    fn link(self) -> Result<Vec<u8>, CompileError> {
+        let headers = HEADER_SIZE + PROGRAM_HEADERS * PROGRAM_HEADER_SIZE;
+        let text_at = headers.next_multiple_of(16);
+        let rodata_at = (text_at + self.text.len() as u64).next_multiple_of(16);
+        let file_size = rodata_at + self.rodata.len() as u64;
+        let bss_at = (BASE + file_size).next_multiple_of(PAGE);
+        let address = |label: &str| -> Result<u64, CompileError> {
+            match self.labels.get(label) {
+                Some((Section::Text, off)) => Ok(BASE + text_at + off),
+                Some((Section::Rodata, off)) => Ok(BASE + rodata_at + off),
+                Some((Section::Bss, off)) => Ok(bss_at + off),
+                None => Err(CompileError::Unsupported(format!("the ELF backend found undefined label `{label}`"))),
+            }
+        };
+
+        let mut text = self.text.clone();
+        for Fixup { at, next, label, addend } in &self.fixups {
+            let rel = address(label)? as i64 + addend - (BASE + text_at + *next as u64) as i64;
+            let rel = i32::try_from(rel).map_err(|_| CompileError::Unsupported(format!("the ELF backend cannot reach `{label}`, it is further than 2 GiB")))?;
+            text[*at..*at + 4].copy_from_slice(&rel.to_le_bytes());
+        }
+
+        let mut f = vec![];
+        // ELF header
+        f.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        f.extend_from_slice(&2u16.to_le_bytes()); // executable
+        f.extend_from_slice(&0x3eu16.to_le_bytes()); // x86-64
+        f.extend_from_slice(&1u32.to_le_bytes());
+        f.extend_from_slice(&address("_start")?.to_le_bytes());
+        f.extend_from_slice(&HEADER_SIZE.to_le_bytes()); // program headers
+        f.extend_from_slice(&0u64.to_le_bytes()); // no section headers
+        f.extend_from_slice(&0u32.to_le_bytes());
+        f.extend_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        f.extend_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+        f.extend_from_slice(&(PROGRAM_HEADERS as u16).to_le_bytes());
+        f.extend_from_slice(&[0; 6]);
+
+        // (type, flags, offset, address, file size, memory size, alignment)
+        let segments = [
+            (1u32, 0b101u32, 0, BASE, file_size, file_size, PAGE),
+            (1, 0b110, 0, bss_at, 0, self.bss, PAGE),
+            // GNU_STACK, non executable stack
+            (0x6474e551, 0b110, 0, 0, 0, 0, 16),
+        ];
+        for (kind, flags, offset, address, file, memory, align) in segments {
+            f.extend_from_slice(&kind.to_le_bytes());
+            f.extend_from_slice(&flags.to_le_bytes());
+            for v in [offset, address, address, file, memory, align] {
+                f.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+
+        f.resize(text_at as usize, 0);
+        f.extend_from_slice(&text);
+        f.resize(rodata_at as usize, 0);
+        f.extend_from_slice(&self.rodata);
+        Ok(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{Eof, Tape, Terminal};
+
+    fn hex(b: &[u8]) -> String {
+        b.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// every form of instruction `x86_64::emit` writes, bytes from GNU as, jumps and rip relative operands are not linked yet
+    #[test]
+    fn encodings() {
+        let table = [
+            ("add byte ptr [r12 + r13*1], -1", "4380042cff"),
+            ("add byte ptr [r12 + rdx*1], al", "41000414"),
+            ("add dl, '0'", "80c230"),
+            ("add dword ptr [r12 + r13*4], -1", "438304acff"),
+            ("add dword ptr [r12 + rdx*4], eax", "41010494"),
+            ("add qword ptr [r12 + r13*8], -1", "4b8304ecff"),
+            ("add qword ptr [r12 + rdx*8], rax", "490104d4"),
+            ("add r13, 30000", "4981c530750000"),
+            ("add r13, 1", "4983c501"),
+            ("add r13, rax", "4901c5"),
+            ("add rax, [rip + bf_timespec + 8]", "48030500000000"),
+            ("add rax, rcx", "4801c8"),
+            ("add rdx, 30000", "4881c230750000"),
+            ("add rdx, 1", "4883c201"),
+            ("add rdx, rax", "4801c2"),
+            ("add rsi, rax", "4801c6"),
+            ("add word ptr [r12 + r13*2], -1", "664383046cff"),
+            ("add word ptr [r12 + rdx*2], ax", "6641010454"),
+            ("and dword ptr [rip + bf_termios_new + 12], -11", "832500000000f5"),
+            ("call bf_flush", "e800000000"),
+            ("cmp byte ptr [r12 + r13*1], 0", "43803c2c00"),
+            ("cmp byte ptr [rip + bf_tty], 0", "803d0000000000"),
+            ("cmp dword ptr [r12 + r13*4], 0", "43833cac00"),
+            ("cmp eax, 10", "83f80a"),
+            ("cmp qword ptr [r12 + r13*8], 0", "4b833cec00"),
+            ("cmp r13, 29996", "4981fd2c750000"),
+            ("cmp r13, 1", "4983fd01"),
+            ("cmp r13, rax", "4939c5"),
+            ("cmp r14, 100", "4983fe64"),
+            ("cmp r15, 4096", "4981ff00100000"),
+            ("cmp rax, 1", "4883f801"),
+            ("cmp rax, [rip + bf_deadline]", "483b0500000000"),
+            ("cmp rdi, rcx", "4839cf"),
+            ("cmp word ptr [r12 + r13*2], 0", "6643833c6c00"),
+            ("dec rdi", "48ffcf"),
+            ("div rcx", "48f7f1"),
+            ("imul rax, rax, 1", "486bc001"),
+            ("inc r14", "49ffc6"),
+            ("inc r15", "49ffc7"),
+            ("inc rdi", "48ffc7"),
+            ("ja ext_site", "0f8700000000"),
+            ("jae ext_site", "0f8300000000"),
+            ("jb ext_num_copy", "0f8200000000"),
+            ("jc ext_site", "0f8200000000"),
+            ("je ext_exit", "0f8400000000"),
+            ("jg ext_site", "0f8f00000000"),
+            ("jle ext_write_done", "0f8e00000000"),
+            ("jmp ext_loop", "e900000000"),
+            ("jne ext_read_eof", "0f8500000000"),
+            ("jnz ext_clock", "0f8500000000"),
+            ("js bf_no_tape", "0f8800000000"),
+            ("jz ext_write_done", "0f8400000000"),
+            ("lea rcx, [rip + bf_num + 32]", "488d0d00000000"),
+            ("lea rdi, [rip + bf_num + 32]", "488d3d00000000"),
+            ("lea rdi, [rip + bf_termios_new]", "488d3d00000000"),
+            ("lea rdx, [rip + bf_out]", "488d1500000000"),
+            ("lea rsi, [rip + ext_msg]", "488d3500000000"),
+            ("mov [rdi], dl", "8817"),
+            ("mov [rdx + r15], al", "4288043a"),
+            ("mov [rip + bf_deadline], rax", "48890500000000"),
+            ("mov al, 10", "b00a"),
+            ("mov al, [rdi]", "8a07"),
+            ("mov al, byte ptr [r12 + r13*1]", "438a042c"),
+            ("mov byte ptr [r12 + r13*1], 0", "43c6042c00"),
+            ("mov byte ptr [r12 + r13*1], al", "4388042c"),
+            ("mov byte ptr [rip + bf_tty], 1", "c6050000000001"),
+            ("mov dword ptr [r12 + r13*4], 0", "43c704ac00000000"),
+            ("mov dword ptr [r12 + r13*4], eax", "438904ac"),
+            ("mov eax, 228", "b8e4000000"),
+            ("mov eax, 1", "b801000000"),
+            ("mov eax, 9", "b809000000"),
+            ("mov eax, dword ptr [r12 + r13*4]", "438b04ac"),
+            ("mov ecx, 10", "b90a000000"),
+            ("mov edi, 1", "bf01000000"),
+            ("mov edx, 1", "ba01000000"),
+            ("mov edx, 3", "ba03000000"),
+            ("mov esi, 0x5401", "be01540000"),
+            ("mov qword ptr [r12 + r13*8], 0", "4bc704ec00000000"),
+            ("mov qword ptr [r12 + r13*8], rax", "4b8904ec"),
+            ("mov r10d, 0x4022", "41ba22400000"),
+            ("mov r12, rax", "4989c4"),
+            ("mov r13, 0", "49c7c500000000"),
+            ("mov r8, -1", "49c7c0ffffffff"),
+            ("mov rax, 4999999996", "48b8fcf1052a01000000"),
+            ("mov rax, -1", "48c7c0ffffffff"),
+            ("mov rax, [rip + bf_timespec]", "488b0500000000"),
+            ("mov rax, qword ptr [r12 + r13*8]", "4b8b04ec"),
+            ("mov rcx, 1000000000", "48c7c100ca9a3b"),
+            ("mov rdx, r13", "4c89ea"),
+            ("mov rdx, r15", "4c89fa"),
+            ("mov rsi, 30000", "48c7c630750000"),
+            ("mov rsi, 70368744177664", "48be0000000000400000"),
+            ("mov word ptr [r12 + r13*2], 0", "6643c7046c0000"),
+            ("mov word ptr [r12 + r13*2], ax", "664389046c"),
+            ("movzx eax, byte ptr [r12 + r13*1]", "430fb6042c"),
+            ("movzx eax, byte ptr [rip + bf_in]", "0fb60500000000"),
+            ("movzx eax, word ptr [r12 + r13*2]", "430fb7046c"),
+            ("mul rcx", "48f7e1"),
+            ("or dword ptr [rip + bf_termios_new + 12], 10", "830d000000000a"),
+            ("pop r11", "415b"),
+            ("pop rax", "58"),
+            ("pop rcx", "59"),
+            ("pop rdi", "5f"),
+            ("pop rdx", "5a"),
+            ("pop rsi", "5e"),
+            ("push r11", "4153"),
+            ("push rax", "50"),
+            ("push rcx", "51"),
+            ("push rdi", "57"),
+            ("push rdx", "52"),
+            ("push rsi", "56"),
+            ("rep movsb", "f3a4"),
+            ("ret", "c3"),
+            ("sub byte ptr [r12 + r13*1], 1", "43802c2c01"),
+            ("sub dword ptr [r12 + r13*4], 1", "43832cac01"),
+            ("sub qword ptr [r12 + r13*8], 1", "4b832cec01"),
+            ("sub r13, 29996", "4981ed2c750000"),
+            ("sub r13, 1", "4983ed01"),
+            ("sub r13, rax", "4929c5"),
+            ("sub rdx, 29998", "4881ea2e750000"),
+            ("sub rdx, 1", "4883ea01"),
+            ("sub rdx, rax", "4829c2"),
+            ("sub word ptr [r12 + r13*2], 1", "6643832c6c01"),
+            ("syscall", "0f05"),
+            ("test r14d, 1023", "41f7c6ff030000"),
+            ("test rax, rax", "4885c0"),
+            ("test rdx, rdx", "4885d2"),
+            ("xor eax, eax", "31c0"),
+            ("xor edi, edi", "31ff"),
+            ("xor edx, edx", "31d2"),
+            ("xor r14d, r14d", "4531f6"),
+            ("xor r15d, r15d", "4531ff"),
+            ("xor r9d, r9d", "4531c9"),
+        ];
+        for (line, bytes) in table {
+            let mut a = Assembler::default();
+            assert_eq!(a.line(line), Some(()), "{line}");
+            assert_eq!(hex(&a.text), bytes, "{line}");
+        }
+    }
+
+    #[test]
+    fn unknown_lines() {
+        for line in ["vzeroupper", "mov xmm0, rax", "add rax, 4999999996", "jxx somewhere", "mov rax, [rsp + rax*3]", ".ascii \"\\", ".balign 3"] {
+            assert_eq!(Assembler::default().line(line), None, "{line}");
+        }
+        let mut a = Assembler::default();
+        a.line("jmp nowhere");
+        assert!(a.link().is_err());
+    }
+
+    /// a program using every op assembles with every setting the x86-64 backends take
+    #[test]
+    fn every_setting() {
+        let code = ",[->+>++<<]>[-]<[>]<[<<]+++.!,>>>>-<<<<[.-]";
+        for cell_bits in [8, 16, 32, 64] {
+            for tape in [Tape::Fixed, Tape::Wrap] {
+                let base = Settings { cell_bits, tape, ..Default::default() };
+                let variants = [
+                    base.clone(),
+                    Settings { check_overflow: true, ..base.clone() },
+                    Settings { max_steps: Some(100), ..base.clone() },
+                    Settings { max_steps: Some(u64::MAX), timeout_ms: Some(5), ..base.clone() },
+                    Settings { timeout_ms: Some(5), ..base.clone() },
+                    Settings { terminal: Terminal::Cooked, ..base.clone() },
+                    Settings { terminal: Terminal::Untouched, ..base.clone() },
+                    Settings { override_new_line_to_null: true, ..base.clone() },
+                    Settings { eof: Eof::Zero, ..base.clone() },
+                    Settings { eof: Eof::Unchanged, ..base.clone() },
+                    Settings { cell_count: 3, ..base.clone() },
+                    Settings { cell_count: x86_64::MAX_TAPE / (cell_bits / 8) as u64, ..base.clone() },
+                ];
+                for settings in variants {
+                    assert!(check(&settings).is_ok());
+                    let mut program = Program::parse(code).expect("valid program");
+                    crate::opt::optimize(&mut program, &settings);
+                    crate::opt::eliminate_bounds_checks(&mut program, &settings);
+                    let f = emit(&program, "a\"b\\?.bf", &settings);
+                    assert!(f.is_ok_and(|f| f.starts_with(b"\x7fELF")), "{settings:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tape_size_limits() {
+        let settings = Settings { cell_count: x86_64::MAX_TAPE, ..Default::default() };
+        assert!(check(&settings).is_ok());
+        let f = emit(&Program::parse("+").expect("valid program"), "a", &settings).expect("fits");
+        // memory size of the second program header, the tape is mapped at start and is not in bss
+        let at = (HEADER_SIZE + PROGRAM_HEADER_SIZE + 40) as usize;
+        let bss = u64::from_le_bytes(f[at..at + 8].try_into().expect("8 bytes"));
+        assert!(bss < 1 << 16, "{bss}");
+        assert!(check(&Settings { cell_count: x86_64::MAX_TAPE + 1, ..Default::default() }).is_err());
+        assert!(check(&Settings { cell_count: u64::MAX, cell_bits: 64, ..Default::default() }).is_err());
+
+        let mut a = Assembler::default();
+        assert_eq!(a.line(".section .bss"), Some(()));
+        assert_eq!(a.line(".skip 18446744073709551615"), None);
+        assert_eq!(a.line(&format!(".skip {MAX_BSS}")), Some(()));
+        assert_eq!(a.line(".skip 1"), None);
+    }
+}
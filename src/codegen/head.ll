@@ -0,0 +1,95 @@
+;  Brainfck compiler
+;  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+;
+;  This program is free software: you can redistribute it and/or modify
+;  it under the terms of the GNU General Public License as published by
+;  the Free Software Foundation, either version 3 of the License, or
+;  (at your option) any later version.
+;
+;  This program is distributed in the hope that it will be useful,
+;  but WITHOUT ANY WARRANTY; without even the implied warranty of
+;  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;  GNU General Public License for more details.
+;
+;  You should have received a copy of the GNU General Public License
+;  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+
+; @hello = private constant [13 x i8] c"Hello world!\00"
+; @prompt = private constant [3 x i8] c"> \00"
+@i_print = private constant [4 x i8] c"%i\0A\00"
+@u32_print = private constant [4 x i8] c"%u\0A\00"
+@u64_print = private constant [6 x i8] c"%llu\0A\00"
+
+@unimplemented = private constant [14 x i8] c"unimplemented\00"
+@none = private constant [5 x i8] c"none\00"
+@panic_f = private constant [33 x i8] c"\0Apanicked: %s | at %s:%llu:%llu\0A\00";
+@alloc_failed = private constant [24 x i8] c"failed to allocate tape\00";
+
+declare void @printf(i8*, ...) nounwind
+declare i32 @getchar() nounwind
+declare void @putchar(i8) nounwind
+declare void @puts(i8*) nounwind
+declare ptr @calloc(i64, i64) nounwind
+declare ptr @realloc(ptr, i64) nounwind
+declare void @free(ptr) nounwind
+declare i32 @clock_gettime(i32, ptr) nounwind
+declare void @dprintf(i32, ptr, ...) nounwind
+declare i32 @fflush(ptr) nounwind
+declare ptr @fopen(ptr, ptr) nounwind
+declare i64 @fwrite(ptr, i64, i64, ptr) nounwind
+declare i32 @fclose(ptr) nounwind
+declare ptr @memchr(ptr, i32, i64) nounwind readonly
+declare ptr @memrchr(ptr, i32, i64) nounwind readonly
+
+; grows tape `*tape` of `*len` cells to at least `need` and at most `max` cells by doubling, new cells are zeroed,
+; returns false and leaves the tape as it was when out of memory
+define private i1 @tape_grow(ptr %tape, ptr %len, i64 %need, i64 %max, i64 %cell_bytes) {
+  %old = load ptr, ptr %tape
+  %old_len = load i64, ptr %len
+  %double = shl i64 %old_len, 1
+  %more = icmp ugt i64 %double, %need
+  %want = select i1 %more, i64 %double, i64 %need
+  %over = icmp ugt i64 %want, %max
+  %new_len = select i1 %over, i64 %max, i64 %want
+  %bytes = mul i64 %new_len, %cell_bytes
+  %new = call ptr @realloc(ptr %old, i64 %bytes)
+  %failed = icmp eq ptr %new, null
+  br i1 %failed, label %fail, label %ok
+fail:
+  ret i1 0
+ok:
+  %old_bytes = mul i64 %old_len, %cell_bytes
+  %fresh = getelementptr i8, ptr %new, i64 %old_bytes
+  %fresh_bytes = sub i64 %bytes, %old_bytes
+  call void @llvm.memset.p0.i64(ptr %fresh, i8 0, i64 %fresh_bytes, i1 0)
+  store ptr %new, ptr %tape
+  store i64 %new_len, ptr %len
+  ret i1 1
+}
+
+; 1-based line of char `pos` by binary search in ascending char indices at which `lines` lines start
+define private i64 @line_of(i64 %pos, ptr %starts, i64 %lines) {
+entry:
+  br label %search
+search:
+  ; the line is in [lo, hi)
+  %lo = phi i64 [ 0, %entry ], [ %next_lo, %halve ]
+  %hi = phi i64 [ %lines, %entry ], [ %next_hi, %halve ]
+  %size = sub i64 %hi, %lo
+  %found = icmp ule i64 %size, 1
+  br i1 %found, label %done, label %halve
+halve:
+  %half = lshr i64 %size, 1
+  %mid = add i64 %lo, %half
+  %mid_ptr = getelementptr i64, ptr %starts, i64 %mid
+  %mid_start = load i64, ptr %mid_ptr
+  %after = icmp ule i64 %mid_start, %pos
+  %next_lo = select i1 %after, i64 %mid, i64 %lo
+  %next_hi = select i1 %after, i64 %hi, i64 %mid
+  br label %search
+done:
+  %line = add i64 %lo, 1
+  ret i64 %line
+}
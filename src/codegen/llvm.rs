@@ -0,0 +1,891 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::fmt::Write as _;
+use crate::interpreter::{BOUNDS_L_PANIC_MSG, OVERFLOW_PANIC_MSG, STEP_LIMIT_MSG, TIME_LIMIT_MSG, bounds_r_panic_msg};
+use crate::interpreter::{LIMIT_EXIT_CODE, PANIC_EXIT_CODE};
+use crate::codegen::prelude::Platform;
+use crate::codegen::{CLOCK_INTERVAL, Escape, StepCheck, escape};
+use crate::error::CompileError;
+use crate::ir::{Node, Op, Program};
+use crate::settings::{Eof, Settings, Tape};
+use crate::token::Span;
+
+const PREABLE: &str = include_str!("head.ll");
+
+/// errors for settings which generated code cannot do on `Settings::target`, `emit` expects them checked
+pub fn check(settings: &Settings) -> Result<(), CompileError> {
+    Platform::new(settings).check(settings)
+}
+
+/// generates LLVM IR of whole module
+pub fn emit(program: &Program, source_name: &str, settings: &Settings) -> String {
+    let mut g = Gen {
+        f: String::new(),
+        settings,
+        platform: Platform::new(settings),
+        cell: cell_type(settings.cell_bits),
+        index: 0,
+        _consts: vec![],
+        p: String::new(),
+        arr: "%arr".to_string(),
+        base: String::new(),
+        off: 0,
+        stored: 0,
+        checked: (0, 0),
+    };
+    write!(g.f, "source_filename = \"{}\"\n", source_name);
+    g.platform.write(&mut g.f, settings.terminal);
+    let cells = settings.initial_cells(program);
+    // a program built by hand may have no table
+    let line_starts = match &program.line_starts[..] {
+        [] => &[0][..],
+        l => l,
+    };
+    write_init(&mut g.f, settings, cells, g.cell, line_starts.len());
+    g._consts.push(("source_name".to_string(), source_name.as_bytes().to_vec()));
+
+    g.write_start(program);
+    g.reload();
+    g.block(&program.ops);
+    g.materialize();
+
+    let Gen { mut f, mut _consts, cell, arr, .. } = g;
+    write!(f, "; ------- END ------ ;\n");
+    if settings.dump_on_exit {
+        f += &dump_call(settings, cells, "end");
+    }
+    // write!(f, "  call void @putchar(i8 10)\n");
+    write!(f, "  br label %exit\n");
+    write!(f, "exit:\n");
+    if settings.tape == Tape::Growable {
+        write!(f, "  %arr_end = load ptr, ptr %tape\n");
+        write!(f, "  call void @free(ptr %arr_end)\n");
+    } else {
+        write!(f, "  call void @free(ptr {arr})\n");
+    }
+    write!(f, "  %exit_v = load i8, ptr %exit_code\n");
+    write!(f, "  ret i8 %exit_v\n");
+    write!(f, "}}\n");
+    if !program.tape.is_empty() {
+        let cells: Vec<String> = program.tape.iter().map(|v| format!("{cell} {v}")).collect();
+        write!(f, "@tape_init = private constant [{} x {cell}] [{}]\n", cells.len(), cells.join(", "));
+    }
+    let starts: Vec<String> = line_starts.iter().map(|s| format!("i64 {s}")).collect();
+    write!(f, "@line_starts = private constant [{} x i64] [{}]\n", starts.len(), starts.join(", "));
+    if settings.dump_on_panic || settings.dump_on_exit {
+        write_dump(&mut f, settings, cell, &mut _consts);
+    }
+    for (c_name, c_val) in _consts {
+        write!(f, "@{c_name} = private constant [{} x i8] c\"{}\\00\"\n",c_val.len()+1, escape(&c_val, Escape::Hex));
+    }
+    f
+}
+
+/// calls `@dump_tape` with current tape and data pointer, `s` makes names unique
+fn dump_call(settings: &Settings, cells: u64, s: &str) -> String {
+    let mut f = String::new();
+    let (arr, len) = if settings.tape == Tape::Growable {
+        write!(f, "  %dump_arr_{s} = load ptr, ptr %tape\n");
+        write!(f, "  %dump_len_{s} = load i64, ptr %len\n");
+        (format!("%dump_arr_{s}"), format!("%dump_len_{s}"))
+    } else {
+        ("%arr".to_string(), cells.to_string())
+    };
+    write!(f, "  %dump_pos_{s} = load i64, ptr %pos\n");
+    write!(f, "  call void @dump_tape(ptr {arr}, i64 {len}, i64 %dump_pos_{s})\n");
+    f
+}
+
+/// `@dump_tape` prints data pointer and a window of `Settings::dump_width` cells around it to stderr,
+/// writes whole tape to `Settings::dump_file`
+fn write_dump(f: &mut String, settings: &Settings, cell: &str, consts: &mut Vec<(String, Vec<u8>)>) {
+    let width = settings.dump_width;
+    let half = width / 2;
+    let digits = settings.cell_bits / 4;
+    consts.push(("dump_head_f".to_string(), b"\ntape dump, pointer at %llu\ncells %llu..%llu:".to_vec()));
+    consts.push(("dump_cell_f".to_string(), format!(" %0{digits}llx").into_bytes()));
+    consts.push(("dump_here_f".to_string(), format!(" [%0{digits}llx]").into_bytes()));
+    consts.push(("dump_end_f".to_string(), b"\n".to_vec()));
+    let wide = if cell == "i64" { "add i64 %v, 0" } else { &format!("zext {cell} %v to i64") };
+    write!(f, "
+define private void @dump_tape(ptr %arr, i64 %len, i64 %pos) {{
+entry:
+  call i32 @fflush(ptr null)
+  %half_ok = icmp uge i64 %pos, {half}
+  %from_half = sub i64 %pos, {half}
+  %start = select i1 %half_ok, i64 %from_half, i64 0
+  %want = add i64 %start, {width}
+  %fits = icmp ule i64 %want, %len
+  %end = select i1 %fits, i64 %want, i64 %len
+  call void @dprintf(i32 2, ptr @dump_head_f, i64 %pos, i64 %start, i64 %end)
+  br label %cond
+cond:
+  %k = phi i64 [ %start, %entry ], [ %kn, %body ]
+  %more = icmp ult i64 %k, %end
+  br i1 %more, label %body, label %done
+body:
+  %t = getelementptr {cell}, ptr %arr, i64 %k
+  %v = load {cell}, ptr %t
+  %w = {wide}
+  %here = icmp eq i64 %k, %pos
+  %fmt = select i1 %here, ptr @dump_here_f, ptr @dump_cell_f
+  call void @dprintf(i32 2, ptr %fmt, i64 %w)
+  %kn = add i64 %k, 1
+  br label %cond
+done:
+  call void @dprintf(i32 2, ptr @dump_end_f)
+");
+    match &settings.dump_file {
+        Some(path) => {
+            consts.push(("dump_file".to_string(), path.to_string_lossy().into_owned().into_bytes()));
+            consts.push(("dump_file_mode".to_string(), b"wb".to_vec()));
+            consts.push(("dump_file_failed_f".to_string(), b"failed to write tape to %s\n".to_vec()));
+            write!(f, "  %file = call ptr @fopen(ptr @dump_file, ptr @dump_file_mode)
+  %no_file = icmp eq ptr %file, null
+  br i1 %no_file, label %file_failed, label %write
+write:
+  call i64 @fwrite(ptr %arr, i64 {}, i64 %len, ptr %file)
+  call i32 @fclose(ptr %file)
+  ret void
+file_failed:
+  call void @dprintf(i32 2, ptr @dump_file_failed_f, ptr @dump_file)
+  ret void
+}}
+", settings.cell_bits / 8);
+        }
+        None => { write!(f, "  ret void\n}}\n"); }
+    }
+}
+
+pub(crate) fn cell_type(bits: u32) -> &'static str {
+    match bits {
+        8 => "i8",
+        16 => "i16",
+        32 => "i32",
+        64 => "i64",
+        _ => panic!("unsupported cell width {bits}"),
+    }
+}
+
+/// `cells` are allocated at start, panic position is looked up in `@line_starts` of `lines` entries
+fn write_init(f: &mut String, settings: &Settings, cells: u64, cell: &str, lines: usize) {
+    let cell_bytes = cell[1..].parse::<usize>().expect("integer type") / 8;
+    let r_msg = bounds_r_panic_msg(settings.tape_limit().unwrap_or(cells));
+    // growable tape is moved by `@tape_grow`
+    let growable = if settings.tape == Tape::Growable {
+        format!("  %tape = alloca ptr\n  store ptr %arr, ptr %tape\n  %len = alloca i64\n  store i64 {cells}, ptr %len\n")
+    } else {
+        String::new()
+    };
+    // loop iterations so far and when `Settings::timeout_ms` runs out
+    let mut limits = String::new();
+    if settings.max_steps.is_some() || settings.timeout_ms.is_some() {
+        limits += "  %steps = alloca i64\n  store i64 0, ptr %steps\n";
+    }
+    if let Some(ms) = settings.timeout_ms {
+        limits += &format!("  %deadline = alloca i64\n  %start_ns = call i64 @now_ns()\n  %end_ns = add i64 %start_ns, {}\n  store i64 %end_ns, ptr %deadline\n", ms.saturating_mul(1_000_000).min(i64::MAX as u64));
+    }
+    let dump = if settings.dump_on_panic { dump_call(settings, cells, "panic") } else { String::new() };
+    write!(f, "{}\n", PREABLE);
+    write!(f, "
+@bounds_r_panic_msg = private constant [{} x i8] c\"{r_msg}\\00\";
+@bounds_l_panic_msg = private constant [{} x i8] c\"{BOUNDS_L_PANIC_MSG}\\00\";
+@overflow_panic_msg = private constant [{} x i8] c\"{OVERFLOW_PANIC_MSG}\\00\";
+@step_limit_msg = private constant [{} x i8] c\"{STEP_LIMIT_MSG}\\00\";
+@time_limit_msg = private constant [{} x i8] c\"{TIME_LIMIT_MSG}\\00\";
+
+define i8 @code() {{
+init:
+  %exit_code = alloca i8
+  store i8 0, ptr %exit_code
+
+  %panic_msg = alloca i8*
+  %panic_pos = alloca i64
+  store i8* @none, ptr %panic_msg
+  %arr = call ptr @calloc(i64 {cells}, i64 {cell_bytes})
+{growable}{limits}  %pos = alloca i64
+  store i64 0, ptr %pos
+  %tape_null = icmp eq ptr %arr, null
+  br i1 %tape_null, label %no_tape, label %code
+no_tape:
+  call void @puts(ptr @alloc_failed)
+  store i8 1, ptr %exit_code
+  br label %exit
+panic:
+  store i8 {PANIC_EXIT_CODE}, ptr %exit_code
+  br label %report
+limit:
+  store i8 {LIMIT_EXIT_CODE}, ptr %exit_code
+  br label %report
+report:
+  %msg = load ptr, i8* %panic_msg
+  %p_pos = load i64, ptr %panic_pos
+  %p_line = call i64 @line_of(i64 %p_pos, ptr @line_starts, i64 {lines})
+  %p_line_i = sub i64 %p_line, 1
+  %p_line_ptr = getelementptr i64, ptr @line_starts, i64 %p_line_i
+  %p_line_start = load i64, ptr %p_line_ptr
+  %p_col_0 = sub i64 %p_pos, %p_line_start
+  %p_col = add i64 %p_col_0, 1
+{dump}  call void @printf(ptr @panic_f, ptr %msg, ptr @source_name, i64 %p_line, i64 %p_col)
+  br label %exit
+code:
+", r_msg.len() + 1, BOUNDS_L_PANIC_MSG.len() + 1, OVERFLOW_PANIC_MSG.len() + 1, STEP_LIMIT_MSG.len() + 1, TIME_LIMIT_MSG.len() + 1);
+}
+
+/// data pointer stored to `%pos` before a panic so that the tape dump shows it
+#[derive(Clone)]
+enum At {
+    /// `p + off`
+    Off(isize),
+    /// i64 value
+    Value(String),
+    /// `%pos` is up to date
+    Stored,
+}
+
+/// lowering state, the data pointer is `p + off` where `p` was loaded from `%pos` and `off` is known statically,
+/// `%pos` is written back only at loop boundaries and I/O
+struct Gen<'a> {
+    f: String,
+    settings: &'a Settings,
+    platform: Platform,
+    /// LLVM type of a cell
+    cell: &'static str,
+    /// makes names unique
+    index: usize,
+    _consts: Vec<(String, Vec<u8>)>,
+    /// `%pos` loaded at start of block
+    p: String,
+    /// start of the tape
+    arr: String,
+    /// address of cell at `p`
+    base: String,
+    off: isize,
+    /// offset from `p` currently held by `%pos`
+    stored: isize,
+    /// range of offsets from `p` which are already bounds checked
+    checked: (isize, isize),
+}
+
+impl Gen<'_> {
+    fn next(&mut self) -> usize {
+        self.index += 1;
+        self.index - 1
+    }
+
+    /// initial tape, data pointer and output of partially evaluated program
+    fn write_start(&mut self, program: &Program) {
+        if !program.tape.is_empty() {
+            let len = program.tape.len() * self.settings.cell_bits as usize / 8;
+            write!(self.f, "  call void @llvm.memcpy.p0.p0.i32(ptr %arr, ptr @tape_init, i32 {len}, i1 0)\n");
+        }
+        if program.start != 0 {
+            write!(self.f, "  store i64 {}, ptr %pos\n", program.start);
+        }
+        if !program.output.is_empty() {
+            let len = program.output.len();
+            self._consts.push(("prefix_out".to_string(), program.output.clone()));
+            write!(self.f, "  br label %prefix_out\n");
+            write!(self.f, "prefix_out:\n");
+            write!(self.f, "  %prefix_k = phi i32 [ 0, %code ], [ %prefix_kn, %prefix_out ]\n");
+            write!(self.f, "  %prefix_t = getelementptr i8, ptr @prefix_out, i32 %prefix_k\n");
+            write!(self.f, "  %prefix_c = load i8, ptr %prefix_t\n");
+            write!(self.f, "  call void @putchar(i8 %prefix_c)\n");
+            write!(self.f, "  %prefix_kn = add i32 %prefix_k, 1\n");
+            write!(self.f, "  %prefix_done = icmp eq i32 %prefix_kn, {len}\n");
+            write!(self.f, "  br i1 %prefix_done, label %prefix_end, label %prefix_out\n");
+            write!(self.f, "prefix_end:\n");
+        }
+    }
+
+    /// `n` wrapped to signed value of cell width
+    fn imm(&self, n: i64) -> i64 {
+        match self.settings.cell_bits {
+            8 => n as i8 as i64,
+            16 => n as i16 as i64,
+            32 => n as i32 as i64,
+            _ => n,
+        }
+    }
+
+    /// zero extends or truncates integer `v`
+    fn convert(&mut self, v: &str, from: &str, to: &str) -> String {
+        let bits = |t: &str| t[1..].parse::<u32>().expect("integer type");
+        let op = match bits(from).cmp(&bits(to)) {
+            std::cmp::Ordering::Equal => { return v.to_string(); }
+            std::cmp::Ordering::Less => "zext",
+            std::cmp::Ordering::Greater => "trunc",
+        };
+        let i = self.next();
+        write!(self.f, "  %cv{i} = {op} {from} {v} to {to}\n");
+        format!("%cv{i}")
+    }
+
+    /// starts a new block with pointer from `%pos`
+    fn reload(&mut self) {
+        let i = self.next();
+        write!(self.f, "  %p{i} = load i64, ptr %pos\n");
+        self.p = format!("%p{i}");
+        self.reload_tape();
+        self.off = 0;
+        self.stored = 0;
+        self.checked = (0, 0);
+    }
+
+    /// derives `base` from `p` again, a growable tape may have moved since
+    fn reload_tape(&mut self) {
+        let i = self.next();
+        if self.settings.tape == Tape::Growable {
+            write!(self.f, "  %arr{i} = load ptr, ptr %tape\n");
+            self.arr = format!("%arr{i}");
+        }
+        write!(self.f, "  %base{i} = getelementptr {}, ptr {}, i64 {}\n", self.cell, self.arr, self.p);
+        self.base = format!("%base{i}");
+    }
+
+    /// current length of a growable tape
+    fn tape_len(&mut self) -> Option<String> {
+        if self.settings.tape != Tape::Growable {
+            return None;
+        }
+        let i = self.next();
+        write!(self.f, "  %tl{i} = load i64, ptr %len\n");
+        Some(format!("%tl{i}"))
+    }
+
+    /// grows the tape to at least `need` cells and continues at `then`
+    fn grow(&mut self, need: &str, then: &str) {
+        let i = self.next();
+        let max = self.settings.max_cells.map_or(-1, |m| m as i64);
+        let bytes = self.settings.cell_bits / 8;
+        write!(self.f, "  %grew{i} = call i1 @tape_grow(ptr %tape, ptr %len, i64 {need}, i64 {max}, i64 {bytes})\n");
+        write!(self.f, "  br i1 %grew{i}, label %{then}, label %no_tape\n");
+    }
+
+    /// `out` = `x` modulo cell count, position on a ring tape
+    fn wrap(&mut self, out: &str, x: &str) {
+        let i = self.next();
+        let cell_count = self.settings.cell_count;
+        write!(self.f, "  %wr{i} = srem i64 {x}, {cell_count}\n");
+        write!(self.f, "  %wn{i} = icmp slt i64 %wr{i}, 0\n");
+        write!(self.f, "  %wa{i} = add i64 %wr{i}, {cell_count}\n");
+        write!(self.f, "  {out} = select i1 %wn{i}, i64 %wa{i}, i64 %wr{i}\n");
+    }
+
+    /// stores `p + off` to `%pos`
+    fn materialize(&mut self) {
+        if self.off != self.stored {
+            let i = self.next();
+            let (p, off) = (&self.p, self.off);
+            write!(self.f, "  %pos{i} = add i64 {p}, {off}\n");
+            write!(self.f, "  store i64 %pos{i}, ptr %pos\n");
+            self.stored = off;
+        }
+    }
+
+    /// address of cell at `p + off`
+    fn cell(&mut self, off: isize) -> String {
+        if off == 0 {
+            return self.base.clone();
+        }
+        let i = self.next();
+        write!(self.f, "  %a{i} = getelementptr {}, ptr {}, i64 {off}\n", self.cell, self.base);
+        format!("%a{i}")
+    }
+
+    /// i1 which is true when `p + off` is outside of the tape,
+    /// right end is `len` of a growable tape or `Settings::tape_limit` without it
+    fn oob(&mut self, off: isize, len: Option<&str>) -> String {
+        let i = self.next();
+        let limit = self.settings.tape_limit().map(|l| l as i128);
+        if off > 0 && let Some(len) = len {
+            write!(self.f, "  %e{i} = add i64 {}, {off}\n", self.p);
+            write!(self.f, "  %oob{i} = icmp uge i64 %e{i}, {len}\n");
+        } else if off > 0 && limit.is_none() {
+            write!(self.f, "  %oob{i} = icmp ne i64 0, 0\n");
+        } else if off > 0 && off as i128 >= limit.unwrap() {
+            write!(self.f, "  %oob{i} = icmp eq i64 0, 0\n");
+        } else if off > 0 {
+            write!(self.f, "  %oob{i} = icmp ugt i64 {}, {}\n", self.p, limit.unwrap() - 1 - off as i128);
+        } else {
+            write!(self.f, "  %oob{i} = icmp ult i64 {}, {}\n", self.p, -off);
+        }
+        format!("%oob{i}")
+    }
+
+    /// scan `i` reached the end of a growable tape at `at`, which is a zero cell once the tape grows
+    fn scan_beyond(&mut self, i: usize, at: &str) {
+        write!(self.f, "beyond{i}:\n");
+        match self.settings.max_cells {
+            Some(max) => {
+                write!(self.f, "  %hard{i} = icmp uge i64 {at}, {max}\n");
+                write!(self.f, "  br i1 %hard{i}, label %bounds_panic{i}, label %grow{i}\n");
+            }
+            None => { write!(self.f, "  br label %grow{i}\n"); }
+        }
+        write!(self.f, "grow{i}:\n");
+        write!(self.f, "  %need{i} = add i64 {at}, 1\n");
+        self.grow(&format!("%need{i}"), &format!("grown{i}"));
+        write!(self.f, "grown{i}:\n");
+        write!(self.f, "  store i64 {at}, ptr %pos\n");
+        write!(self.f, "  br label %scan_end{i}\n");
+    }
+
+    fn panic_block(&mut self, label: &str, msg: &str, span: Span, at: At) {
+        self.report_block(label, msg, span, "panic", at);
+    }
+
+    /// block reporting `msg` at `span` through `to`, which is `panic` or `limit`
+    fn report_block(&mut self, label: &str, msg: &str, span: Span, to: &str, at: At) {
+        write!(self.f, "{label}:\n");
+        write!(self.f, "  store i64 {}, ptr %panic_pos\n", span.pos);
+        write!(self.f, "  store i8* {msg}, ptr %panic_msg\n");
+        if self.settings.dump_on_panic {
+            match at {
+                At::Off(off) => {
+                    let i = self.next();
+                    write!(self.f, "  %at{i} = add i64 {}, {off}\n", self.p);
+                    write!(self.f, "  store i64 %at{i}, ptr %pos\n");
+                }
+                At::Value(v) => { write!(self.f, "  store i64 {v}, ptr %pos\n"); }
+                At::Stored => {}
+            }
+        }
+        write!(self.f, "  br label %{to}\n");
+    }
+
+    /// `StepCheck` of a loop iteration, `at` is the pointer reported by a limit
+    fn count_step(&mut self, span: Span, at: At) {
+        let Some(check) = StepCheck::new(self.settings) else { return; };
+        let i = self.next();
+        write!(self.f, "  %st{i} = load i64, ptr %steps\n");
+        write!(self.f, "  %st{i}n = add i64 %st{i}, 1\n");
+        write!(self.f, "  store i64 %st{i}n, ptr %steps\n");
+        if let Some(max) = check.max {
+            write!(self.f, "  %over{i} = icmp ugt i64 %st{i}n, {}\n", max.min(i64::MAX as u64));
+            write!(self.f, "  br i1 %over{i}, label %step_limit{i}, label %steps_ok{i}\n");
+            self.report_block(&format!("step_limit{i}"), "@step_limit_msg", span, "limit", at.clone());
+            write!(self.f, "steps_ok{i}:\n");
+        }
+        if check.clock {
+            write!(self.f, "  %tick{i} = and i64 %st{i}n, {}\n", CLOCK_INTERVAL - 1);
+            write!(self.f, "  %check{i} = icmp eq i64 %tick{i}, 0\n");
+            write!(self.f, "  br i1 %check{i}, label %clock{i}, label %time_ok{i}\n");
+            write!(self.f, "clock{i}:\n");
+            write!(self.f, "  %now{i} = call i64 @now_ns()\n");
+            write!(self.f, "  %end{i} = load i64, ptr %deadline\n");
+            write!(self.f, "  %late{i} = icmp sgt i64 %now{i}, %end{i}\n");
+            write!(self.f, "  br i1 %late{i}, label %time_limit{i}, label %time_ok{i}\n");
+            self.report_block(&format!("time_limit{i}"), "@time_limit_msg", span, "limit", at);
+            write!(self.f, "time_ok{i}:\n");
+        }
+    }
+
+    /// message of a bounds panic when moving by `off`
+    fn bounds_msg(off: isize) -> &'static str {
+        if off > 0 { "@bounds_r_panic_msg" } else { "@bounds_l_panic_msg" }
+    }
+
+    /// one check for all moves of a run, on failure the move which would panic first is searched for
+    fn check_moves(&mut self, moves: &[(isize, isize, Span)]) {
+        let (lo, hi) = self.checked;
+        let new_hi = moves.iter().map(|m| m.0).max().unwrap_or(hi).max(hi);
+        let new_lo = moves.iter().map(|m| m.0).min().unwrap_or(lo).min(lo);
+        if new_hi == hi && new_lo == lo {
+            return;
+        }
+        let i = self.next();
+        let len = if new_hi > hi { self.tape_len() } else { None };
+        let cond = match (new_hi > hi, new_lo < lo) {
+            (true, true) => {
+                let r = self.oob(new_hi, len.as_deref());
+                let l = self.oob(new_lo, None);
+                write!(self.f, "  %oob{i} = or i1 {r}, {l}\n");
+                format!("%oob{i}")
+            }
+            (true, false) => self.oob(new_hi, len.as_deref()),
+            _ => self.oob(new_lo, None),
+        };
+        write!(self.f, "  br i1 {cond}, label %bounds{i}_0, label %continue{i}\n");
+
+        let (mut h, mut l) = (hi, lo);
+        let mut k = 0;
+        for &(off, before, span) in moves {
+            if off <= h && off >= l {
+                continue;
+            }
+            h = h.max(off);
+            l = l.min(off);
+            write!(self.f, "bounds{i}_{k}:\n");
+            let c = self.oob(off, None);
+            write!(self.f, "  br i1 {c}, label %bounds_panic{i}_{k}, label %bounds{i}_{}\n", k + 1);
+            self.panic_block(&format!("bounds_panic{i}_{k}"), Self::bounds_msg(off), span, At::Off(before));
+            k += 1;
+        }
+        write!(self.f, "bounds{i}_{k}:\n");
+        if len.is_some() {
+            // no move panics, so a growable tape is too short
+            write!(self.f, "  %need{i} = add i64 {}, {}\n", self.p, new_hi + 1);
+            self.grow(&format!("%need{i}"), &format!("continue{i}"));
+            write!(self.f, "continue{i}:\n");
+            self.reload_tape();
+        } else {
+            write!(self.f, "  unreachable\n");
+            write!(self.f, "continue{i}:\n");
+        }
+        self.checked = (new_lo, new_hi);
+    }
+
+    fn block(&mut self, ops: &[Node]) {
+        let mut rest = ops;
+        while !rest.is_empty() {
+            let mut n = rest.iter().take_while(|n| matches!(n.op, Op::Add(_) | Op::Move(_) | Op::SetZero)).count();
+            if self.settings.check_overflow {
+                // moves after an add which can panic are checked only once it did not
+                n = rest[..n].iter().position(|n| matches!(n.op, Op::Add(_))).map_or(n, |a| a + 1);
+            }
+            if self.settings.dump_on_panic {
+                // moves are checked before cells change, so that a dump shows the cells as they were at the panic
+                if let Some(c) = rest[..n].iter().position(|n| !matches!(n.op, Op::Move(_))) {
+                    n = rest[c..n].iter().position(|n| matches!(n.op, Op::Move(_))).map_or(n, |m| c + m);
+                }
+            }
+            if n == 0 {
+                self.process_op(&rest[0]);
+                rest = &rest[1..];
+                continue;
+            }
+            let (run, r) = rest.split_at(n);
+            rest = r;
+
+            let mut off = self.off;
+            // ring tape wraps the pointer instead
+            let wrap = self.settings.tape == Tape::Wrap;
+            let moves: Vec<(isize, isize, Span)> = run.iter().filter_map(|n| match n.op {
+                Op::Move(m) => {
+                    off += m;
+                    (!n.in_bounds && !wrap).then_some((off, off - m, n.span))
+                }
+                _ => None,
+            }).collect();
+            self.check_moves(&moves);
+            for node in run {
+                self.process_op(node);
+            }
+        }
+    }
+
+    fn process_op(&mut self, node: &Node) {
+        let c = self.cell;
+        let Node { op, span, in_bounds } = node;
+        let file_pos = span.pos;
+        let cell_count = self.settings.cell_count;
+        let i = self.next();
+
+        // comment start
+        match op {
+            Op::Loop(_) => { write!(self.f, "; ---- {i} | Loop | at char {} ----\n", file_pos+1); }
+            op => { write!(self.f, "; ---- {i} | {:?} | at char {} ----\n", op, file_pos+1); }
+        }
+        match op {
+            // bounds are checked by `block` for whole run of moves
+            &Op::Move(n) => {
+                self.off += n;
+                if self.settings.tape == Tape::Wrap && !in_bounds {
+                    write!(self.f, "  %x{i} = add i64 {}, {}\n", self.p, self.off);
+                    self.wrap(&format!("%w{i}"), &format!("%x{i}"));
+                    write!(self.f, "  store i64 %w{i}, ptr %pos\n");
+                    self.reload();
+                }
+            }
+            &Op::Add(n) if self.settings.check_overflow => {
+                let t = self.cell(self.off);
+                write!(self.f, "  %c{i} = load {c}, ptr {t}\n");
+                let by = n.unsigned_abs() as u64;
+                let always = by > self.settings.cell_mask();
+                if always {
+                    write!(self.f, "  br label %overflow{i}\n");
+                } else {
+                    let f = if n > 0 { "uadd" } else { "usub" };
+                    write!(self.f, "  %r{i} = call {{{c}, i1}} @llvm.{f}.with.overflow.{c}({c} %c{i}, {c} {})\n", self.imm(by as i64));
+                    write!(self.f, "  %c{i}n = extractvalue {{{c}, i1}} %r{i}, 0\n");
+                    write!(self.f, "  %o{i} = extractvalue {{{c}, i1}} %r{i}, 1\n");
+                    write!(self.f, "  br i1 %o{i}, label %overflow{i}, label %continue{i}\n");
+                }
+                self.panic_block(&format!("overflow{i}"), "@overflow_panic_msg", *span, At::Off(self.off));
+                // without predecessors when the add always overflows
+                write!(self.f, "continue{i}:\n");
+                if !always {
+                    write!(self.f, "  store {c} %c{i}n, ptr {t}\n");
+                }
+            }
+            &Op::Add(n) => {
+                let t = self.cell(self.off);
+                write!(self.f, "  %c{i} = load {c}, ptr {t}\n");
+                write!(self.f, "  %c{i}n = add {c} %c{i}, {}\n", self.imm(n as i64));
+                write!(self.f, "  store {c} %c{i}n, ptr {t}\n");
+            }
+            Op::SetZero => {
+                let t = self.cell(self.off);
+                write!(self.f, "  store {c} 0, ptr {t}\n");
+            }
+            Op::Out => {
+                self.materialize();
+                let t = self.cell(self.off);
+                write!(self.f, "  %c{i} = load {c}, ptr {t}\n");
+                let b = self.convert(&format!("%c{i}"), c, "i8");
+                write!(self.f, "  call void @putchar(i8 {b})\n");
+            }
+            Op::PrintNum => {
+                self.materialize();
+                let t = self.cell(self.off);
+                write!(self.f, "  %c{i} = load {c}, ptr {t}\n");
+                match c {
+                    "i64" => { write!(self.f, "  call void @printf(ptr @u64_print, i64 %c{i})\n"); }
+                    "i32" => { write!(self.f, "  call void @printf(ptr @u32_print, i32 %c{i})\n"); }
+                    _ => {
+                        let n = self.convert(&format!("%c{i}"), c, "i32");
+                        write!(self.f, "  call void @printf(ptr @i_print, i32 {n})\n");
+                    }
+                }
+            }
+            Op::In => {
+                self.materialize();
+                write!(self.f, "  %nc{i} = call i32 @getchar()\n");
+                let t = self.cell(self.off);
+                let nc = if self.settings.override_new_line_to_null {
+                    write!(self.f, "  %nl_is{i} = icmp eq i32 %nc{i}, 10\n");
+                    write!(self.f, "  %nsc{i} = select i1 %nl_is{i}, i32 0, i32 %nc{i}\n");
+                    format!("%nsc{i}")
+                } else {
+                    format!("%nc{i}")
+                };
+                // a byte is 0..=255, EOF is -1
+                let nc = self.convert(&nc, "i32", c);
+                let eof = match self.settings.eof {
+                    Eof::Zero => "0".to_string(),
+                    Eof::MinusOne => "-1".to_string(),
+                    Eof::Unchanged => {
+                        write!(self.f, "  %old{i} = load {c}, ptr {t}\n");
+                        format!("%old{i}")
+                    }
+                };
+                write!(self.f, "  %eof_is{i} = icmp eq i32 %nc{i}, -1\n");
+                write!(self.f, "  %in{i} = select i1 %eof_is{i}, {c} {eof}, {c} {nc}\n");
+                write!(self.f, "  store {c} %in{i}, ptr {t}\n");
+            }
+            &Op::MulAdd(offset, factor) => {
+                let t = self.off + offset;
+                let cur = self.cell(self.off);
+                write!(self.f, "  %c{i} = load {c}, ptr {cur}\n");
+                let target = if self.settings.tape == Tape::Wrap && !in_bounds {
+                    write!(self.f, "  %x{i} = add i64 {}, {t}\n", self.p);
+                    self.wrap(&format!("%w{i}"), &format!("%x{i}"));
+                    write!(self.f, "  %tw{i} = getelementptr {c}, ptr {}, i64 %w{i}\n", self.arr);
+                    format!("%tw{i}")
+                } else if !in_bounds && (t < self.checked.0 || t > self.checked.1) {
+                    // bounds check, only matters when the loop would have run
+                    let oob = self.oob(t, None);
+                    write!(self.f, "  %nz{i} = icmp ne {c} %c{i}, 0\n");
+                    write!(self.f, "  %bc{i} = and i1 %nz{i}, {oob}\n");
+                    write!(self.f, "  br i1 %bc{i}, label %bounds_panic{i}, label %continue{i}\n");
+                    self.panic_block(&format!("bounds_panic{i}"), Self::bounds_msg(t), *span, At::Off(self.off));
+                    write!(self.f, "continue{i}:\n");
+                    let skip = match self.tape_len().filter(|_| t > 0) {
+                        Some(len) => {
+                            let short = self.oob(t, Some(&len));
+                            write!(self.f, "  %ng{i} = and i1 %nz{i}, {short}\n");
+                            write!(self.f, "  br i1 %ng{i}, label %grow{i}, label %grown{i}\n");
+                            write!(self.f, "grow{i}:\n");
+                            write!(self.f, "  %need{i} = add i64 {}, {}\n", self.p, t + 1);
+                            self.grow(&format!("%need{i}"), &format!("grown{i}"));
+                            write!(self.f, "grown{i}:\n");
+                            self.reload_tape();
+                            write!(self.f, "  %skip{i} = xor i1 %ng{i}, {short}\n");
+                            format!("%skip{i}")
+                        }
+                        None => oob,
+                    };
+                    let (cur, target) = (self.cell(self.off), self.cell(t));
+                    // out of bounds target is only possible with 0 in the current cell, which then adds 0 to itself
+                    write!(self.f, "  %tt{i} = select i1 {skip}, ptr {cur}, ptr {target}\n");
+                    format!("%tt{i}")
+                } else {
+                    self.cell(t)
+                };
+                write!(self.f, "  %c{i}t = load {c}, ptr {target}\n");
+                write!(self.f, "  %m{i} = mul {c} %c{i}, {}\n", self.imm(factor as i64));
+                write!(self.f, "  %c{i}n = add {c} %c{i}t, %m{i}\n");
+                write!(self.f, "  store {c} %c{i}n, ptr {target}\n");
+            }
+            &Op::Scan(n) => {
+                self.materialize();
+                let (cond, msg) = if n > 0 { ("uge", "@bounds_r_panic_msg") } else { ("ugt", "@bounds_l_panic_msg") };
+                let t = self.cell(self.off);
+                write!(self.f, "  %pos{i} = add i64 {}, {}\n", self.p, self.off);
+                write!(self.f, "  %c{i} = load {c}, ptr {t}\n");
+                write!(self.f, "  %z{i} = icmp eq {c} %c{i}, 0\n");
+                write!(self.f, "  br i1 %z{i}, label %scan_end{i}, label %scan{i}\n");
+                write!(self.f, "scan{i}:\n");
+                // memchr searches bytes only and does not count iterations
+                let memchr = (n == 1 || n == -1 && self.platform.has_memrchr()) && c == "i8" && self.settings.tape != Tape::Wrap && self.settings.max_steps.is_none();
+                // position before the move which left the tape
+                let last = if memchr {
+                    At::Value(if n > 0 { self.settings.tape_limit().unwrap_or(1) - 1 } else { 0 }.to_string())
+                } else {
+                    At::Value(format!("%q{i}"))
+                };
+                match n {
+                    _ if memchr => {
+                        let arr = self.arr.clone();
+                        let end = if n == 1 {
+                            let end = self.tape_len();
+                            write!(self.f, "  %len{i} = sub i64 {}, %pos{i}\n", end.as_deref().unwrap_or(&cell_count.to_string()));
+                            write!(self.f, "  %r{i} = call ptr @memchr(ptr {t}, i32 0, i64 %len{i})\n");
+                            end
+                        } else {
+                            write!(self.f, "  %len{i} = add i64 %pos{i}, 1\n");
+                            write!(self.f, "  %r{i} = call ptr @memrchr(ptr {arr}, i32 0, i64 %len{i})\n");
+                            None
+                        };
+                        write!(self.f, "  %nf{i} = icmp eq ptr %r{i}, null\n");
+                        let miss = if end.is_some() { "beyond" } else { "bounds_panic" };
+                        write!(self.f, "  br i1 %nf{i}, label %{miss}{i}, label %found{i}\n");
+                        write!(self.f, "found{i}:\n");
+                        write!(self.f, "  %ri{i} = ptrtoint ptr %r{i} to i64\n");
+                        write!(self.f, "  %ai{i} = ptrtoint ptr {arr} to i64\n");
+                        write!(self.f, "  %d{i} = sub i64 %ri{i}, %ai{i}\n");
+                        write!(self.f, "  store i64 %d{i}, ptr %pos\n");
+                        write!(self.f, "  br label %scan_end{i}\n");
+                        if let Some(end) = end {
+                            self.scan_beyond(i, &end);
+                        }
+                    }
+                    _ => {
+                        let end = if n > 0 { self.tape_len() } else { None };
+                        write!(self.f, "  br label %scan_loop{i}\n");
+                        write!(self.f, "scan_loop{i}:\n");
+                        write!(self.f, "  %q{i} = phi i64 [ %pos{i}, %scan{i} ], [ %q{i}n, %scan_next{i} ]\n");
+                        self.count_step(*span, At::Value(format!("%q{i}")));
+                        if self.settings.tape == Tape::Wrap {
+                            write!(self.f, "  %qx{i} = add i64 %q{i}, {n}\n");
+                            self.wrap(&format!("%q{i}n"), &format!("%qx{i}"));
+                            write!(self.f, "  br label %scan_next{i}\n");
+                        } else {
+                            write!(self.f, "  %q{i}n = add i64 %q{i}, {n}\n");
+                            if n > 0 {
+                                write!(self.f, "  %oob{i} = icmp {cond} i64 %q{i}n, {}\n", end.as_deref().unwrap_or(&cell_count.to_string()));
+                            } else {
+                                write!(self.f, "  %oob{i} = icmp {cond} i64 %q{i}n, %q{i}\n");
+                            }
+                            let miss = if end.is_some() { "beyond" } else { "bounds_panic" };
+                            write!(self.f, "  br i1 %oob{i}, label %{miss}{i}, label %scan_next{i}\n");
+                        }
+                        write!(self.f, "scan_next{i}:\n");
+                        write!(self.f, "  %t{i}n = getelementptr {c}, ptr {}, i64 %q{i}n\n", self.arr);
+                        write!(self.f, "  %c{i}n = load {c}, ptr %t{i}n\n");
+                        write!(self.f, "  %z{i}n = icmp eq {c} %c{i}n, 0\n");
+                        write!(self.f, "  br i1 %z{i}n, label %found{i}, label %scan_loop{i}\n");
+                        write!(self.f, "found{i}:\n");
+                        write!(self.f, "  store i64 %q{i}n, ptr %pos\n");
+                        write!(self.f, "  br label %scan_end{i}\n");
+                        if end.is_some() {
+                            self.scan_beyond(i, &format!("%q{i}n"));
+                        }
+                    }
+                }
+                self.panic_block(&format!("bounds_panic{i}"), msg, *span, last);
+                write!(self.f, "scan_end{i}:\n");
+                self.reload();
+            }
+            Op::Loop(body) => {
+                self.materialize();
+                let t = self.cell(self.off);
+                write!(self.f, "  %c{i} = load {c}, ptr {t}\n");
+                write!(self.f, "  %cmp_rs{i} = icmp eq {c} %c{i}, 0\n");
+                write!(self.f, "  br i1 %cmp_rs{i}, label %skip{i}, label %loop{i}\n");
+                write!(self.f, "loop{i}:\n");
+                self.count_step(*span, At::Stored);
+
+                self.reload();
+                self.block(body);
+                self.materialize();
+
+                write!(self.f, "; ---- {i} | LoopEnd ----\n");
+                let t = self.cell(self.off);
+                write!(self.f, "  %c{i}_e = load {c}, ptr {t}\n");
+                write!(self.f, "  %cmp_rs{i}_e = icmp ne {c} %c{i}_e, 0\n");
+                write!(self.f, "  br i1 %cmp_rs{i}_e, label %loop{i}, label %skip{i}\n");
+                write!(self.f, "skip{i}:\n");
+                self.reload();
+            }
+        }
+
+        // end comment
+        write!(self.f, "; ------------\n\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ir(code: &str, settings: &Settings) -> String {
+        emit(&Program::parse(code).expect("valid program"), "a.b", settings)
+    }
+
+    fn settings(cell_count: u64) -> Settings {
+        Settings { cell_count, ..Default::default() }
+    }
+
+    #[test]
+    fn one_bounds_check_per_run() {
+        let ir = ir(">>+<<<-.>", &settings(4));
+        // `>>+<<<-` is checked once, the `>` after output stays within what was checked
+        assert_eq!(ir.matches(", label %continue").count(), 1);
+        // the failing branch looks for the move which leaves the tape first
+        assert_eq!(ir.matches("@bounds_r_panic_msg, ptr %panic_msg").count(), 1);
+        assert_eq!(ir.matches("@bounds_l_panic_msg, ptr %panic_msg").count(), 1);
+        // the pointer is stored only before output and at the end
+        assert_eq!(ir.matches("store i64 %pos").count(), 2);
+    }
+
+    #[test]
+    fn large_tape() {
+        let ir = ir(">", &settings(1 << 40));
+        assert!(ir.contains("@calloc(i64 1099511627776, i64 1)"));
+        assert!(ir.contains("icmp ugt i64 %p0, 1099511627774"));
+    }
+
+    #[test]
+    fn panic_positions() {
+        // `<<<` leaves the tape at char index 7, which is looked up in the table
+        let f = ir("+\r\n>.\r\n<<<", &settings(4));
+        assert!(f.contains("@line_starts = private constant [3 x i64] [i64 0, i64 3, i64 7]\n"), "{f}");
+        assert!(f.contains("  store i64 7, ptr %panic_pos\n"), "{f}");
+        assert!(f.contains("call i64 @line_of(i64 %p_pos, ptr @line_starts, i64 3)"), "{f}");
+    }
+
+    #[test]
+    fn growable_tape_within_max_cells() {
+        let settings = Settings { cell_count: 8, tape: Tape::Growable, max_cells: Some(6), ..Default::default() };
+        let f = ir(">>>>>>.", &settings);
+        assert!(f.contains("call ptr @calloc(i64 6, i64 1)") && f.contains("  store i64 6, ptr %len\n"), "{f}");
+        assert!(f.contains("exceeded bounds check (larger than 6)"), "{f}");
+    }
+}
@@ -0,0 +1,147 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+//! backends and helpers shared by them
+
+pub mod c;
+pub mod elf;
+pub mod llvm;
+pub mod prelude;
+pub mod rust;
+pub mod wat;
+pub mod x86_64;
+
+use std::fmt::Write as _;
+use crate::settings::{Settings, Tape};
+
+/// how `pos + n` is computed and what is checked before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Move {
+    /// wrapping tape, `pos + k` which is `pos - back` once `pos >= back` so that it never overflows
+    Wrap { k: u64, back: u64 },
+    /// `pos + m`, panics first when `limit - pos <= m`, a growable tape then grows when `grow`
+    Right { m: u64, limit: Option<u64>, grow: bool },
+    /// `pos - m`, panics first when `pos < m` if `check`
+    Left { m: u64, check: bool },
+}
+
+impl Move {
+    pub fn new(n: isize, in_bounds: bool, settings: &Settings) -> Move {
+        let m = n.unsigned_abs() as u64;
+        match settings.tape {
+            Tape::Wrap => {
+                let k = (n as i128).rem_euclid(settings.cell_count as i128) as u64;
+                Move::Wrap { k, back: settings.cell_count - k }
+            }
+            _ if n >= 0 => Move::Right {
+                m,
+                limit: settings.tape_limit().filter(|_| !in_bounds),
+                grow: !in_bounds && settings.tape == Tape::Growable,
+            },
+            _ => Move::Left { m, check: !in_bounds },
+        }
+    }
+}
+
+/// loop iterations between two looks at the clock
+pub const CLOCK_INTERVAL: u64 = 1024;
+
+/// what every loop iteration does, it is counted against `max` and the clock is checked every `CLOCK_INTERVAL` of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepCheck {
+    pub max: Option<u64>,
+    pub clock: bool,
+}
+
+impl StepCheck {
+    /// `None` when iterations are not counted at all
+    pub fn new(settings: &Settings) -> Option<StepCheck> {
+        let check = StepCheck { max: settings.max_steps, clock: settings.timeout_ms.is_some() };
+        (check.max.is_some() || check.clock).then_some(check)
+    }
+}
+
+/// how bytes which are not printable ASCII are written in a string literal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Escape {
+    /// `\ooo` of C and GNU assembler, always 3 digits so that a following digit is not taken in,
+    /// `?` too so that C never sees a trigraph
+    Octal,
+    /// `\HH` of LLVM and WebAssembly text, also used for `"` and `\`
+    Hex,
+    /// `\xhh` of Rust byte strings
+    PrefixedHex,
+}
+
+/// escapes bytes for a string literal between `"`
+pub fn escape(s: &[u8], style: Escape) -> String {
+    let mut r = String::with_capacity(s.len());
+    for &b in s {
+        match (b, style) {
+            (b'"' | b'\\', Escape::Hex) => { write!(r, "\\{b:02X}"); }
+            (b'"' | b'\\', _) => { r.push('\\'); r.push(b as char); }
+            (b'?', Escape::Octal) => { write!(r, "\\{b:03o}"); }
+            (b' '..=b'~', _) => { r.push(b as char); }
+            (_, Escape::Octal) => { write!(r, "\\{b:03o}"); }
+            (_, Escape::Hex) => { write!(r, "\\{b:02X}"); }
+            (_, Escape::PrefixedHex) => { write!(r, "\\x{b:02x}"); }
+        }
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_styles() {
+        let s = "a\"b\\c?\n\0\x7f1é".as_bytes();
+        assert_eq!(escape(s, Escape::Octal), "a\\\"b\\\\c\\077\\012\\000\\1771\\303\\251");
+        assert_eq!(escape(s, Escape::Hex), "a\\22b\\5Cc?\\0A\\00\\7F1\\C3\\A9");
+        assert_eq!(escape(s, Escape::PrefixedHex), "a\\\"b\\\\c?\\x0a\\x00\\x7f1\\xc3\\xa9");
+        assert_eq!(escape(b"", Escape::Octal), "");
+    }
+
+    #[test]
+    fn moves() {
+        let fixed = Settings { cell_count: 10, ..Default::default() };
+        assert_eq!(Move::new(3, false, &fixed), Move::Right { m: 3, limit: Some(10), grow: false });
+        assert_eq!(Move::new(3, true, &fixed), Move::Right { m: 3, limit: None, grow: false });
+        assert_eq!(Move::new(-3, false, &fixed), Move::Left { m: 3, check: true });
+        assert_eq!(Move::new(isize::MIN, true, &fixed), Move::Left { m: 1 << 63, check: false });
+        let growable = Settings { tape: Tape::Growable, ..fixed.clone() };
+        assert_eq!(Move::new(3, false, &growable), Move::Right { m: 3, limit: None, grow: true });
+        let growable = Settings { max_cells: Some(20), ..growable };
+        assert_eq!(Move::new(3, false, &growable), Move::Right { m: 3, limit: Some(20), grow: true });
+        let wrap = Settings { tape: Tape::Wrap, ..fixed };
+        assert_eq!(Move::new(3, false, &wrap), Move::Wrap { k: 3, back: 7 });
+        assert_eq!(Move::new(-3, false, &wrap), Move::Wrap { k: 7, back: 3 });
+        assert_eq!(Move::new(20, false, &wrap), Move::Wrap { k: 0, back: 10 });
+        let wrap = Settings { cell_count: u64::MAX, ..wrap };
+        assert_eq!(Move::new(-1, false, &wrap), Move::Wrap { k: u64::MAX - 1, back: 1 });
+    }
+
+    #[test]
+    fn step_checks() {
+        assert_eq!(StepCheck::new(&Settings::default()), None);
+        let settings = Settings { max_steps: Some(5), ..Default::default() };
+        assert_eq!(StepCheck::new(&settings), Some(StepCheck { max: Some(5), clock: false }));
+        let settings = Settings { timeout_ms: Some(5), ..Default::default() };
+        assert_eq!(StepCheck::new(&settings), Some(StepCheck { max: None, clock: true }));
+    }
+}
@@ -0,0 +1,277 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+
+//! parts of generated runtime which differ between targets: `main` with terminal setup and libc constants
+
+use std::fmt::Write as _;
+use crate::error::CompileError;
+use crate::settings::{Settings, Terminal};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Os {
+    Linux,
+    /// macOS and iOS
+    Darwin,
+    /// FreeBSD and DragonFly
+    FreeBsd,
+    OpenBsd,
+    NetBsd,
+    Windows,
+    Other,
+}
+
+impl Os {
+    /// finds system in target triple like `x86_64-unknown-linux-gnu` or in `std::env::consts::OS`
+    fn of(name: &str) -> Self {
+        name.split('-').find_map(|part| match part {
+            _ if part.starts_with("linux") || part == "android" => Some(Os::Linux),
+            _ if part.starts_with("darwin") || part.starts_with("macos") || part.starts_with("ios") => Some(Os::Darwin),
+            _ if part.starts_with("freebsd") || part.starts_with("dragonfly") => Some(Os::FreeBsd),
+            _ if part.starts_with("openbsd") => Some(Os::OpenBsd),
+            _ if part.starts_with("netbsd") => Some(Os::NetBsd),
+            _ if part.starts_with("windows") || part.starts_with("win32") => Some(Os::Windows),
+            _ => None,
+        }).unwrap_or(Os::Other)
+    }
+}
+
+/// layout of `struct termios`
+struct Termios {
+    size: usize,
+    /// byte offset of `c_lflag`
+    lflag: usize,
+    /// type of `tcflag_t`
+    flag: &'static str,
+    /// `ICANON | ECHO`
+    mask: u64,
+}
+
+/// system generated code runs on
+#[derive(Debug, Clone)]
+pub struct Platform {
+    triple: Option<String>,
+    os: Os,
+}
+
+impl Platform {
+    pub fn new(settings: &Settings) -> Self {
+        let os = Os::of(settings.target.as_deref().unwrap_or(std::env::consts::OS));
+        Platform { triple: settings.target.clone(), os }
+    }
+
+    /// errors for settings which generated code cannot do on this platform
+    pub fn check(&self, settings: &Settings) -> Result<(), CompileError> {
+        let name = self.triple.as_deref().unwrap_or(std::env::consts::OS);
+        let unsupported = |what: &str| Err(CompileError::Unsupported(format!("{what} is not supported on target '{name}'")));
+        if let Some(t) = &self.triple {
+            // tape, lengths and libc calls use i64
+            let arch = t.split('-').next().unwrap_or("");
+            // x32 and ilp32 environments have 32-bit pointers on a 64-bit architecture
+            let env = t.rsplit('-').next().unwrap_or("");
+            let wide = arch.contains("64") && !arch.ends_with("_32") || matches!(arch, "s390x" | "sparcv9");
+            if !wide || env.ends_with("x32") || env.ends_with("ilp32") {
+                return unsupported("32-bit architecture");
+            }
+        }
+        if settings.timeout_ms.is_some() && self.clock_monotonic().is_none() {
+            return unsupported("--timeout-ms");
+        }
+        // tape dumps are written by `dprintf`
+        if (settings.dump_on_panic || settings.dump_on_exit) && self.os == Os::Windows {
+            return unsupported("dumping tape");
+        }
+        Ok(())
+    }
+
+    /// `CLOCK_MONOTONIC` of `clock_gettime`
+    fn clock_monotonic(&self) -> Option<i32> {
+        match self.os {
+            Os::Linux => Some(1),
+            Os::Darwin => Some(6),
+            Os::FreeBsd => Some(4),
+            Os::OpenBsd | Os::NetBsd => Some(3),
+            Os::Windows | Os::Other => None,
+        }
+    }
+
+    /// libc has `memrchr`
+    pub fn has_memrchr(&self) -> bool {
+        matches!(self.os, Os::Linux | Os::FreeBsd | Os::OpenBsd | Os::NetBsd)
+    }
+
+    fn termios(&self) -> Option<Termios> {
+        match self.os {
+            Os::Linux => Some(Termios { size: 60, lflag: 12, flag: "i32", mask: 0o2 | 0o10 }),
+            Os::Darwin => Some(Termios { size: 72, lflag: 24, flag: "i64", mask: 0x100 | 0x8 }),
+            Os::FreeBsd | Os::OpenBsd | Os::NetBsd => Some(Termios { size: 44, lflag: 12, flag: "i32", mask: 0x100 | 0x8 }),
+            Os::Windows | Os::Other => None,
+        }
+    }
+
+    /// target triple and definitions of `main` and `@now_ns`, `main` returns exit code of `@code`
+    pub fn write(&self, f: &mut String, terminal: Terminal) {
+        if let Some(t) = &self.triple {
+            write!(f, "target triple = \"{t}\"\n");
+        }
+        if let Some(clock) = self.clock_monotonic() {
+            write!(f, "
+; nanoseconds of monotonic clock
+define private i64 @now_ns() {{
+  %ts = alloca [2 x i64]
+  call i32 @clock_gettime(i32 {clock}, ptr %ts)
+  %sec = load i64, ptr %ts
+  %nsec_ptr = getelementptr i64, ptr %ts, i64 1
+  %nsec = load i64, ptr %nsec_ptr
+  %sec_ns = mul i64 %sec, 1000000000
+  %ns = add i64 %sec_ns, %nsec
+  ret i64 %ns
+}}
+");
+        }
+        let termios = self.termios().filter(|_| terminal != Terminal::Untouched);
+        let Some(Termios { size, lflag, flag, mask }) = termios else {
+            write!(f, "
+define i32 @main() {{
+  %exit_v = call i8 @code()
+  %exit = zext i8 %exit_v to i32
+  ret i32 %exit
+}}
+");
+            return;
+        };
+        let change = match terminal {
+            Terminal::Cooked => format!("or {flag} %c_lflag_val, {mask}"),
+            _ => format!("and {flag} %c_lflag_val, {}", !(mask as i64)),
+        };
+        write!(f, "
+declare i32 @isatty(i32) nounwind
+declare i32 @tcgetattr(i32, ptr) nounwind
+;int tcgetattr(int fd, struct termios *termios_p);
+declare i32 @tcsetattr(i32, i32, ptr)
+;int tcsetattr(int fd, int optional_actions,
+;              const struct termios *termios_p);
+
+define i32 @main() {{
+entry:
+  %old = alloca [{size} x i8], align 8
+  %new = alloca [{size} x i8], align 8
+  %tty = call i32 @isatty(i32 0) ; piped input is left alone
+  %is_tty = icmp ne i32 %tty, 0
+  br i1 %is_tty, label %setup, label %run
+setup:
+  call i32 @tcgetattr(i32 0, ptr %old) ; retrieves present terminal settings so that they could be restored afterwards
+  call void @llvm.memcpy.p0.p0.i32(ptr %new, ptr %old, i32 {size}, i1 0) ; copies old settings
+
+  %c_lflag_ptr = getelementptr i8, ptr %new, i32 {lflag} ; pointer to c_lflag
+  %c_lflag_val = load {flag}, ptr %c_lflag_ptr
+  %new_c_lflag = {change}
+  store {flag} %new_c_lflag, ptr %c_lflag_ptr
+  call i32 @tcsetattr(i32 0, i32 0, ptr %new) ; sets new terminal settings
+  br label %run
+run:
+  %exit_v = call i8 @code()
+  br i1 %is_tty, label %restore, label %done
+restore:
+  call i32 @tcsetattr(i32 0, i32 0, ptr %old) ; restores original terminal settings
+  br label %done
+done:
+  %exit = zext i8 %exit_v to i32
+  ret i32 %exit
+}}
+");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn platform(target: &str) -> Platform {
+        Platform::new(&Settings { target: Some(target.to_string()), ..Default::default() })
+    }
+
+    #[test]
+    fn os_of_triples() {
+        for (triple, os) in [
+            ("x86_64-unknown-linux-gnu", Os::Linux),
+            ("aarch64-linux-android", Os::Linux),
+            ("aarch64-apple-darwin", Os::Darwin),
+            ("x86_64-apple-macosx14.0.0", Os::Darwin),
+            ("aarch64-apple-ios", Os::Darwin),
+            ("x86_64-unknown-freebsd14.1", Os::FreeBsd),
+            ("x86_64-unknown-dragonfly", Os::FreeBsd),
+            ("x86_64-unknown-openbsd", Os::OpenBsd),
+            ("aarch64-unknown-netbsd", Os::NetBsd),
+            ("x86_64-pc-windows-msvc", Os::Windows),
+            ("x86_64-unknown-none", Os::Other),
+        ] {
+            assert_eq!(platform(triple).os, os, "{triple}");
+        }
+    }
+
+    #[test]
+    fn main_per_os() {
+        let main = |triple: &str, terminal| {
+            let mut f = String::new();
+            platform(triple).write(&mut f, terminal);
+            f
+        };
+        let darwin = main("aarch64-apple-darwin", Terminal::Raw);
+        assert!(darwin.starts_with("target triple = \"aarch64-apple-darwin\"\n"), "{darwin}");
+        assert!(darwin.contains("alloca [72 x i8]") && darwin.contains("getelementptr i8, ptr %new, i32 24"), "{darwin}");
+        assert!(darwin.contains("and i64 %c_lflag_val, -265"), "{darwin}");
+        assert!(darwin.contains("call i32 @clock_gettime(i32 6, ptr %ts)"), "{darwin}");
+
+        let freebsd = main("x86_64-unknown-freebsd", Terminal::Cooked);
+        assert!(freebsd.contains("alloca [44 x i8]") && freebsd.contains("or i32 %c_lflag_val, 264"), "{freebsd}");
+        assert!(freebsd.contains("call i32 @clock_gettime(i32 4, ptr %ts)"), "{freebsd}");
+
+        // no termios and no monotonic clock
+        let windows = main("x86_64-pc-windows-msvc", Terminal::Raw);
+        assert!(!windows.contains("tcsetattr") && !windows.contains("@now_ns"), "{windows}");
+        assert!(!main("x86_64-unknown-linux-gnu", Terminal::Untouched).contains("tcsetattr"));
+    }
+
+    #[test]
+    fn unsupported_settings() {
+        let timeout = Settings { timeout_ms: Some(1), ..Default::default() };
+        let dump = Settings { dump_on_exit: true, ..Default::default() };
+        assert!(platform("aarch64-apple-darwin").check(&timeout).is_ok());
+        assert!(platform("aarch64-apple-darwin").check(&dump).is_ok());
+        assert!(platform("x86_64-pc-windows-msvc").check(&timeout).is_err());
+        assert!(platform("x86_64-pc-windows-msvc").check(&dump).is_err());
+        assert!(platform("x86_64-unknown-none").check(&timeout).is_err());
+        assert!(platform("x86_64-unknown-none").check(&dump).is_ok());
+        assert!(platform("x86_64-unknown-linux-gnu").has_memrchr());
+        assert!(!platform("aarch64-apple-darwin").has_memrchr());
+    }
+
+    #[test]
+    fn only_64_bit_architectures() {
+        let settings = Settings::default();
+        for triple in ["x86_64-unknown-linux-gnu", "aarch64-apple-darwin", "riscv64gc-unknown-linux-gnu", "powerpc64le-unknown-linux-gnu",
+                       "s390x-unknown-linux-gnu", "sparcv9-sun-solaris", "x86_64-pc-windows-msvc"] {
+            assert!(platform(triple).check(&settings).is_ok(), "{triple}");
+        }
+        for triple in ["i686-unknown-linux-gnu", "armv7-unknown-linux-gnueabihf", "wasm32-wasip1", "x86_64-unknown-linux-gnux32",
+                       "aarch64_32-apple-watchos", "aarch64-unknown-linux-gnu_ilp32", "riscv32imac-unknown-none-elf"] {
+            let e = platform(triple).check(&settings).unwrap_err();
+            assert_eq!(e.to_string(), format!("error: 32-bit architecture is not supported on target '{triple}'"));
+        }
+    }
+}
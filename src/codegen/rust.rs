@@ -0,0 +1,343 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+
+//! lowering of `ir::Program` to a Rust module with
+//! `pub fn run(input: &mut impl Read, output: &mut impl Write) -> Result<(), BfPanic>`,
+//! input and output errors are ignored the same way `interpreter::run` does
+
+use std::fmt::Write as _;
+use crate::codegen::{CLOCK_INTERVAL, Escape, Move, StepCheck, escape};
+use crate::error::CompileError;
+use crate::interpreter::{BOUNDS_L_PANIC_MSG, OVERFLOW_PANIC_MSG, STEP_LIMIT_MSG, TIME_LIMIT_MSG, bounds_r_panic_msg};
+use crate::interpreter::{LIMIT_EXIT_CODE, PANIC_EXIT_CODE};
+use crate::ir::{Node, Op, Program};
+use crate::settings::{Eof, Settings};
+use crate::token::Span;
+
+/// errors for settings the Rust backend cannot do
+pub fn check(settings: &Settings) -> Result<(), CompileError> {
+    let unsupported = |what: &str| Err(CompileError::Unsupported(format!("{what} is not supported by the Rust backend")));
+    if settings.target.is_some() {
+        return unsupported("--target, the crate including the module picks the target,");
+    }
+    if settings.dump_on_panic || settings.dump_on_exit {
+        return unsupported("dumping tape");
+    }
+    let size = (settings.cell_bits / 8) as u64;
+    if settings.cell_count.checked_mul(size).is_none_or(|bytes| bytes > isize::MAX as u64) {
+        return unsupported(&format!("--cell-count above {} with {}-bit cells", isize::MAX as u64 / size, settings.cell_bits));
+    }
+    Ok(())
+}
+
+/// generates Rust source of a module running whole program
+pub fn emit(program: &Program, source_name: &str, settings: &Settings) -> String {
+    let cells = settings.initial_cells(program);
+    let mut g = Gen {
+        f: String::new(),
+        settings,
+        r_msg: bounds_r_panic_msg(settings.tape_limit().unwrap_or(cells)),
+        depth: 1,
+        reads: false,
+        grows: false,
+        steps: false,
+    };
+    g.block(&program.ops);
+    let Gen { f: body, reads, grows, steps, .. } = g;
+    // everything was run at compile time
+    let tape = !program.ops.is_empty();
+
+    let mut f = String::new();
+    write!(f, "// {}, generated by bf\n\n", source_name.replace('\n', " "));
+    write!(f, "/// file `BfPanic` positions are in\npub const SOURCE: &str = {source_name:?};\n");
+    write!(f, "
+/// runtime error of the program, `exit_code` is {PANIC_EXIT_CODE} for panics and {LIMIT_EXIT_CODE} for step and time limits,
+/// `line` is 0 when the tape could not be allocated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BfPanic {{
+    pub message: &'static str,
+    pub line: usize,
+    pub column: usize,
+    pub exit_code: u8,
+}}
+
+impl std::fmt::Display for BfPanic {{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {{
+        if self.line == 0 {{
+            return write!(f, \"{{}}\", self.message);
+        }}
+        write!(f, \"panicked: {{}} | at {{}}:{{}}:{{}}\", self.message, SOURCE, self.line, self.column)
+    }}
+}}
+
+impl std::error::Error for BfPanic {{}}
+");
+    if tape {
+        write!(f, "\ntype Cell = u{};\n", settings.cell_bits);
+        write!(f, "\nconst NO_TAPE: BfPanic = BfPanic {{ message: \"failed to allocate tape\", line: 0, column: 0, exit_code: {PANIC_EXIT_CODE} }};\n");
+    }
+    if tape && !program.tape.is_empty() {
+        let init: Vec<String> = program.tape.iter().map(|v| v.to_string()).collect();
+        write!(f, "\nconst INIT: [Cell; {}] = [{}];\n", init.len(), init.join(", "));
+    }
+    if !program.output.is_empty() {
+        write!(f, "\n/// output of the part run at compile time\nconst PREFIX: &[u8] = b\"{}\";\n", escape(&program.output, Escape::PrefixedHex));
+    }
+
+    write!(f, "
+/// runs the program, output is flushed when it stops
+pub fn run(input: &mut impl std::io::Read, output: &mut impl std::io::Write) -> Result<(), BfPanic> {{
+    let result = code(input, output);
+    _ = output.flush();
+    result
+}}
+");
+    // a program which never reads a cell or input leaves some of them unused
+    write!(f, "
+#[allow(unused_mut, unused_variables, unused_assignments)]
+fn code(input: &mut impl std::io::Read, output: &mut impl std::io::Write) -> Result<(), BfPanic> {{
+");
+    if tape {
+        write!(f, "    let mut tape: Vec<Cell> = Vec::new();\n    tape.try_reserve_exact({cells}).map_err(|_| NO_TAPE)?;\n    tape.resize({cells}, 0);\n");
+        if !program.tape.is_empty() {
+            write!(f, "    tape[..INIT.len()].copy_from_slice(&INIT);\n");
+        }
+        write!(f, "    let mut pos: usize = {};\n", program.start);
+    }
+    if steps {
+        write!(f, "    let mut steps: u64 = 0;\n");
+    }
+    if let Some(ms) = settings.timeout_ms {
+        write!(f, "    let deadline = std::time::Instant::now() + std::time::Duration::from_millis({ms});\n");
+    }
+    if !program.output.is_empty() {
+        write!(f, "    _ = output.write_all(PREFIX);\n");
+    }
+    f += &body;
+    write!(f, "    Ok(())\n}}\n");
+
+    if reads {
+        let byte = match settings.cell_bits {
+            8 => "b[0]",
+            _ => "Cell::from(b[0])",
+        };
+        let byte = match settings.override_new_line_to_null {
+            true => format!("if b[0] == b'\\n' {{ 0 }} else {{ {byte} }}"),
+            false => byte.to_string(),
+        };
+        write!(f, "
+/// ',' with output flushed first, `None` at the end of input or on error
+fn read(input: &mut impl std::io::Read, output: &mut impl std::io::Write) -> Option<Cell> {{
+    _ = output.flush();
+    let mut b = [0u8];
+    match input.read(&mut b) {{
+        Ok(1) => Some({byte}),
+        _ => None,
+    }}
+}}
+");
+    }
+    if grows {
+        let max = settings.max_cells.map(|m| format!(".min({m})")).unwrap_or_default();
+        write!(f, "
+/// grows tape to at least `need` cells by doubling, new cells are zeroed
+fn grow(tape: &mut Vec<Cell>, need: usize) -> Result<(), BfPanic> {{
+    let want = (tape.len() * 2).max(need){max};
+    tape.try_reserve_exact(want - tape.len()).map_err(|_| NO_TAPE)?;
+    tape.resize(want, 0);
+    Ok(())
+}}
+");
+    }
+    f
+}
+
+struct Gen<'a> {
+    f: String,
+    settings: &'a Settings,
+    r_msg: String,
+    /// indentation
+    depth: usize,
+    /// `read` is used
+    reads: bool,
+    /// `grow` is used
+    grows: bool,
+    /// loop iterations are counted
+    steps: bool,
+}
+
+impl Gen<'_> {
+    fn line(&mut self, s: &str) {
+        write!(self.f, "{:1$}{s}\n", "", self.depth * 4);
+    }
+
+    /// `return Err(..)` of `msg` at `span`
+    fn panic(&self, msg: &str, span: Span, code: u8) -> String {
+        format!("return Err(BfPanic {{ message: {msg:?}, line: {}, column: {}, exit_code: {code} }});", span.line, span.col)
+    }
+
+    fn block(&mut self, ops: &[Node]) {
+        for node in ops {
+            self.process_op(node);
+        }
+    }
+
+    /// `target = pos + n` where target is `pos` or `let t`, bounds checked or wrapped, a growable tape grows to it
+    fn offset(&mut self, target: &str, n: isize, span: Span, in_bounds: bool) {
+        let var = target.trim_start_matches("let ");
+        match Move::new(n, in_bounds, self.settings) {
+            Move::Wrap { k: 0, .. } => {
+                if var != "pos" {
+                    self.line(&format!("{target} = pos;"));
+                }
+            }
+            Move::Wrap { k, back } => { self.line(&format!("{target} = if pos >= {back} {{ pos - {back} }} else {{ pos + {k} }};")); }
+            Move::Right { m, limit, grow } => {
+                if let Some(limit) = limit {
+                    let panic = self.panic(&self.r_msg, span, PANIC_EXIT_CODE);
+                    self.line(&format!("if {limit} - pos <= {m} {{ {panic} }}"));
+                }
+                match target {
+                    "pos" => { self.line(&format!("pos += {m};")); }
+                    _ => { self.line(&format!("{target} = pos + {m};")); }
+                }
+                if grow {
+                    self.grows = true;
+                    self.line(&format!("if {var} >= tape.len() {{ grow(&mut tape, {var} + 1)?; }}"));
+                }
+            }
+            Move::Left { m, check } => {
+                if check {
+                    let panic = self.panic(BOUNDS_L_PANIC_MSG, span, PANIC_EXIT_CODE);
+                    self.line(&format!("if pos < {m} {{ {panic} }}"));
+                }
+                match target {
+                    "pos" => { self.line(&format!("pos -= {m};")); }
+                    _ => { self.line(&format!("{target} = pos - {m};")); }
+                }
+            }
+        }
+    }
+
+    /// `StepCheck` of a loop iteration in `steps`
+    fn count_step(&mut self, span: Span) {
+        let Some(check) = StepCheck::new(self.settings) else { return; };
+        self.steps = true;
+        self.line("steps += 1;");
+        if let Some(max) = check.max {
+            let panic = self.panic(STEP_LIMIT_MSG, span, LIMIT_EXIT_CODE);
+            self.line(&format!("if steps > {max} {{ {panic} }}"));
+        }
+        if check.clock {
+            let panic = self.panic(TIME_LIMIT_MSG, span, LIMIT_EXIT_CODE);
+            self.line(&format!("if steps & {} == 0 && std::time::Instant::now() > deadline {{ {panic} }}", CLOCK_INTERVAL - 1));
+        }
+    }
+
+    fn process_op(&mut self, node: &Node) {
+        let Node { op, span, in_bounds } = node;
+        let span = *span;
+        let mask = self.settings.cell_mask();
+        match op {
+            &Op::Move(n) => { self.offset("pos", n, span, *in_bounds); }
+            &Op::Add(n) => {
+                let m = n.unsigned_abs() as u64;
+                if self.settings.check_overflow {
+                    let panic = self.panic(OVERFLOW_PANIC_MSG, span, PANIC_EXIT_CODE);
+                    // a constant condition would leave the rest unreachable
+                    let cond = match n {
+                        _ if m > mask => format!("u64::from(tape[pos]) + {m} > {mask}"),
+                        1.. => format!("tape[pos] > {}", mask - m),
+                        _ => format!("tape[pos] < {m}"),
+                    };
+                    self.line(&format!("if {cond} {{ {panic} }}"));
+                }
+                self.line(&format!("tape[pos] = tape[pos].wrapping_add({});", n as i64 as u64 & mask));
+            }
+            Op::Out => {
+                let cell = if self.settings.cell_bits == 8 { "tape[pos]" } else { "tape[pos] as u8" };
+                self.line(&format!("_ = output.write_all(&[{cell}]);"));
+            }
+            Op::In => {
+                self.reads = true;
+                let eof = match self.settings.eof {
+                    Eof::Zero => Some(0),
+                    Eof::MinusOne => Some(mask),
+                    Eof::Unchanged => None,
+                };
+                match eof {
+                    Some(v) => { self.line(&format!("tape[pos] = read(input, output).unwrap_or({v});")); }
+                    None => { self.line("if let Some(c) = read(input, output) { tape[pos] = c; }"); }
+                }
+            }
+            Op::PrintNum => { self.line("_ = writeln!(output, \"{}\", tape[pos]);"); }
+            Op::SetZero => { self.line("tape[pos] = 0;"); }
+            Op::Loop(body) => {
+                self.line("while tape[pos] != 0 {");
+                self.depth += 1;
+                self.count_step(span);
+                self.block(body);
+                self.depth -= 1;
+                self.line("}");
+            }
+            &Op::Scan(n) => {
+                self.line("while tape[pos] != 0 {");
+                self.depth += 1;
+                self.count_step(span);
+                self.offset("pos", n, span, false);
+                self.depth -= 1;
+                self.line("}");
+            }
+            &Op::MulAdd(offset, factor) => {
+                self.line("if tape[pos] != 0 {");
+                self.depth += 1;
+                self.offset("let t", offset, span, *in_bounds);
+                self.line(&format!("tape[t] = tape[t].wrapping_add(tape[pos].wrapping_mul({}));", factor as i64 as u64 & mask));
+                self.depth -= 1;
+                self.line("}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Tape;
+
+    #[test]
+    fn tape_size_limit() {
+        let settings = |cell_count, cell_bits| Settings { cell_count, cell_bits, ..Default::default() };
+        assert!(check(&settings(isize::MAX as u64, 8)).is_ok());
+        assert!(check(&settings(isize::MAX as u64 / 8, 64)).is_ok());
+        assert!(check(&settings(isize::MAX as u64 + 1, 8)).is_err());
+        assert!(check(&settings(1 << 62, 16)).is_err());
+        assert!(check(&settings(u64::MAX, 64)).is_err());
+    }
+
+    #[test]
+    fn tape_allocation_is_reported() {
+        let settings = Settings { cell_count: 4, tape: Tape::Growable, ..Default::default() };
+        let mut program = Program::parse(",[>+]").expect("valid program");
+        crate::opt::optimize(&mut program, &settings);
+        let f = emit(&program, "a.b", &settings);
+        assert!(f.contains("    tape.try_reserve_exact(4).map_err(|_| NO_TAPE)?;\n"), "{f}");
+        assert!(f.contains("grow(&mut tape, pos + 1)?;"), "{f}");
+        assert!(f.contains("fn grow(tape: &mut Vec<Cell>, need: usize) -> Result<(), BfPanic> {"), "{f}");
+    }
+}
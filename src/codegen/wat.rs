@@ -0,0 +1,576 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+
+//! lowering of `ir::Program` to a WebAssembly text module, input and output go through WASI
+//!
+//! linear memory holds scratch space, the output buffer, messages and then the tape,
+//! the data pointer `$pos` is the byte offset of the current cell, a panic prints its message and traps
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use crate::codegen::{CLOCK_INTERVAL, Escape, Move, StepCheck, escape};
+use crate::error::CompileError;
+use crate::interpreter::{BOUNDS_L_PANIC_MSG, OVERFLOW_PANIC_MSG, STEP_LIMIT_MSG, TIME_LIMIT_MSG, bounds_r_panic_msg};
+use crate::interpreter::{LIMIT_EXIT_CODE, PANIC_EXIT_CODE};
+use crate::ir::{Node, Op, Program};
+use crate::settings::{Eof, Settings, Tape};
+use crate::token::Span;
+
+/// iovec of `fd_read` and `fd_write`
+const IOVEC: u64 = 0;
+/// bytes read or written
+const COUNT: u64 = 8;
+/// result of `clock_time_get`
+const TIME: u64 = 16;
+/// byte read by ','
+const IN: u64 = 24;
+/// digits of '!' are written backwards to here
+const NUM_END: u64 = 56;
+const OUT: u64 = 64;
+/// bytes written at once
+const OUT_SIZE: u64 = 4096;
+/// output of partial evaluation and messages
+const DATA: u64 = OUT + OUT_SIZE;
+const PAGE: u64 = 65536;
+/// 32-bit linear memory
+const MEMORY: u64 = 1 << 32;
+/// memory which is not the tape is at most this
+const RESERVED: u64 = 1 << 24;
+const NO_TAPE_MSG: &str = "failed to allocate tape\n";
+
+/// errors for settings the WebAssembly backend cannot do
+pub fn check(settings: &Settings) -> Result<(), CompileError> {
+    let unsupported = |what: &str| Err(CompileError::Unsupported(format!("{what} is not supported by the WebAssembly backend")));
+    if settings.target.as_deref().is_some_and(|t| !t.starts_with("wasm32")) {
+        return unsupported("target other than wasm32");
+    }
+    if settings.dump_on_panic || settings.dump_on_exit {
+        return unsupported("dumping tape");
+    }
+    let size = (settings.cell_bits / 8) as u64;
+    if settings.cell_count > (MEMORY - RESERVED) / size {
+        return unsupported(&format!("--cell-count above {} with {}-bit cells", (MEMORY - RESERVED) / size, settings.cell_bits));
+    }
+    Ok(())
+}
+
+/// generates WebAssembly text of whole program, `_start` is the entry point and `memory` is exported
+pub fn emit(program: &Program, source_name: &str, settings: &Settings) -> String {
+    let size = (settings.cell_bits / 8) as u64;
+    let cells = settings.initial_cells(program);
+    // messages are before the tape, the first run only finds out how many bytes they take
+    let data = Gen::new(program, source_name, settings, 0).run(&program.ops).data.len() as u64;
+    let tape = (DATA + data).next_multiple_of(64);
+    let g = Gen::new(program, source_name, settings, tape).run(&program.ops);
+    let Gen { f: body, ty, end, data, reads, prints, panics, no_tape, .. } = g;
+
+    let mut f = String::new();
+    let timeout = settings.timeout_ms.is_some();
+    let growable = settings.tape == Tape::Growable;
+
+    write!(f, ";; {}\n(module\n", source_name.replace('\n', " "));
+    let wasi = "\"wasi_snapshot_preview1\"";
+    write!(f, "  (import {wasi} \"fd_write\" (func $fd_write (param i32 i32 i32 i32) (result i32)))\n");
+    if reads {
+        write!(f, "  (import {wasi} \"fd_read\" (func $fd_read (param i32 i32 i32 i32) (result i32)))\n");
+    }
+    write!(f, "  (import {wasi} \"proc_exit\" (func $proc_exit (param i32)))\n");
+    if timeout {
+        write!(f, "  (import {wasi} \"clock_time_get\" (func $clock_time_get (param i32 i64 i32) (result i32)))\n");
+    }
+    write!(f, "  (memory (export \"memory\") {})\n", (tape + cells * size).div_ceil(PAGE).max(1));
+    write!(f, "  (global $out_len (mut i32) (i32.const 0))\n");
+    if growable {
+        write!(f, "  ;; bytes of the tape\n  (global $len (mut i32) (i32.const {}))\n", cells * size);
+    }
+    if !data.is_empty() {
+        write!(f, "  (data (i32.const {DATA}) \"{}\")\n", escape(&data, Escape::Hex));
+    }
+    if !program.tape.is_empty() {
+        let init: Vec<u8> = program.tape.iter().flat_map(|v| v.to_le_bytes()[..size as usize].to_vec()).collect();
+        write!(f, "  (data (i32.const {tape}) \"{}\")\n", escape(&init, Escape::Hex));
+    }
+
+    write_runtime(&mut f, settings, ty, tape, end, reads, prints, panics, no_tape);
+
+    write!(f, "\n  (func $main (export \"_start\")\n    (local $pos i32) (local $t i32) (local $steps i64) (local $deadline i64)\n");
+    write!(f, "    (local.set $pos (i32.const {}))\n", program.start as u64 * size);
+    if !program.output.is_empty() {
+        write!(f, "    (call $write (i32.const {DATA}) (i32.const {}))\n", program.output.len());
+    }
+    if let Some(ms) = settings.timeout_ms {
+        write!(f, "    (local.set $deadline (i64.add (call $now) (i64.const {})))\n", ms.saturating_mul(1_000_000).min(i64::MAX as u64));
+    }
+    f += &body;
+    write!(f, "    (call $flush))\n)\n");
+    f
+}
+
+/// `$write`, `$flush`, `$putc` and, when used, `$print_num`, `$input`, `$now`, `$grow`, `$no_tape` and `$report`
+#[allow(clippy::too_many_arguments)]
+fn write_runtime(f: &mut String, settings: &Settings, ty: Type, tape: u64, end: u64, reads: bool, prints: bool, panics: bool, no_tape: Option<u64>) {
+    write!(f, "
+  ;; writes all `len` bytes at `ptr` to stdout
+  (func $write (param $ptr i32) (param $len i32)
+    (block $done
+      (loop $more
+        (br_if $done (i32.eqz (local.get $len)))
+        (i32.store (i32.const {IOVEC}) (local.get $ptr))
+        (i32.store (i32.const {}) (local.get $len))
+        (br_if $done (call $fd_write (i32.const 1) (i32.const {IOVEC}) (i32.const 1) (i32.const {COUNT})))
+        (br_if $done (i32.eqz (i32.load (i32.const {COUNT}))))
+        (local.set $ptr (i32.add (local.get $ptr) (i32.load (i32.const {COUNT}))))
+        (local.set $len (i32.sub (local.get $len) (i32.load (i32.const {COUNT}))))
+        (br $more))))
+
+  (func $flush
+    (call $write (i32.const {OUT}) (global.get $out_len))
+    (global.set $out_len (i32.const 0)))
+
+  (func $putc (param $c i32)
+    (i32.store8 offset={OUT} (global.get $out_len) (local.get $c))
+    (global.set $out_len (i32.add (global.get $out_len) (i32.const 1)))
+    (if (i32.eq (global.get $out_len) (i32.const {OUT_SIZE})) (then (call $flush))))
+", IOVEC + 4);
+    if prints {
+        write!(f, "
+  ;; `v` in decimal and new line
+  (func $print_num (param $v i64)
+    (local $p i32)
+    (local.set $p (i32.const {NUM_END}))
+    (loop $digit
+      (local.set $p (i32.sub (local.get $p) (i32.const 1)))
+      (i32.store8 (local.get $p) (i32.wrap_i64 (i64.add (i64.rem_u (local.get $v) (i64.const 10)) (i64.const 48))))
+      (local.set $v (i64.div_u (local.get $v) (i64.const 10)))
+      (br_if $digit (i64.ne (local.get $v) (i64.const 0))))
+    (loop $put
+      (call $putc (i32.load8_u (local.get $p)))
+      (local.set $p (i32.add (local.get $p) (i32.const 1)))
+      (br_if $put (i32.lt_u (local.get $p) (i32.const {NUM_END}))))
+    (call $putc (i32.const 10)))
+");
+    }
+    if reads {
+        let c = ty.extend("(local.get $c)");
+        let eof = match settings.eof {
+            Eof::Zero => format!("{} ", ty.store(tape, "(local.get $at)", &format!("({}.const 0)", ty.name))),
+            Eof::MinusOne => format!("{} ", ty.store(tape, "(local.get $at)", &format!("({}.const {})", ty.name, settings.cell_mask()))),
+            Eof::Unchanged => String::new(),
+        };
+        write!(f, "
+  ;; ',' into cell at `at`, with output flushed first
+  (func $input (param $at i32)
+    (local $c i32)
+    (call $flush)
+    (i32.store (i32.const {IOVEC}) (i32.const {IN}))
+    (i32.store (i32.const {}) (i32.const 1))
+    (if (i32.or (call $fd_read (i32.const 0) (i32.const {IOVEC}) (i32.const 1) (i32.const {COUNT})) (i32.eqz (i32.load (i32.const {COUNT}))))
+      (then {eof}(return)))
+    (local.set $c (i32.load8_u (i32.const {IN})))
+", IOVEC + 4);
+        if settings.override_new_line_to_null {
+            write!(f, "    (if (i32.eq (local.get $c) (i32.const 10)) (then (local.set $c (i32.const 0))))\n");
+        }
+        write!(f, "    {})\n", ty.store(tape, "(local.get $at)", &c));
+    }
+    if settings.timeout_ms.is_some() {
+        write!(f, "
+  ;; nanoseconds of monotonic clock
+  (func $now (result i64)
+    (drop (call $clock_time_get (i32.const 1) (i64.const 1000) (i32.const {TIME})))
+    (i64.load (i32.const {TIME})))
+");
+    }
+    if settings.tape == Tape::Growable {
+        write!(f, "
+  ;; grows tape to at least `need` bytes by doubling, new memory is zeroed
+  (func $grow (param $need i32)
+    (local $want i64) (local $pages i64)
+    (local.set $want (i64.shl (i64.extend_i32_u (global.get $len)) (i64.const 1)))
+    (if (i64.lt_u (local.get $want) (i64.extend_i32_u (local.get $need))) (then (local.set $want (i64.extend_i32_u (local.get $need)))))
+    (if (i64.gt_u (local.get $want) (i64.const {end})) (then (local.set $want (i64.const {end}))))
+    (local.set $pages (i64.sub (i64.shr_u (i64.add (local.get $want) (i64.const {})) (i64.const 16)) (i64.extend_i32_u (memory.size))))
+    (if (i64.gt_s (local.get $pages) (i64.const 0))
+      (then (if (i32.eq (memory.grow (i32.wrap_i64 (local.get $pages))) (i32.const -1)) (then (call $no_tape)))))
+    (global.set $len (i32.wrap_i64 (local.get $want))))
+", tape + PAGE - 1);
+    }
+    if let Some(msg) = no_tape {
+        write!(f, "
+  (func $no_tape
+    (call $flush)
+    (call $write (i32.const {msg}) (i32.const {}))
+    (call $proc_exit (i32.const {PANIC_EXIT_CODE})))
+", NO_TAPE_MSG.len());
+    }
+    if panics {
+        write!(f, "
+  ;; prints message, a panic traps and a limit exits with `code`
+  (func $report (param $ptr i32) (param $len i32) (param $code i32)
+    (call $flush)
+    (call $write (local.get $ptr) (local.get $len))
+    (if (i32.eq (local.get $code) (i32.const {PANIC_EXIT_CODE})) (then (unreachable)))
+    (call $proc_exit (local.get $code)))
+");
+    }
+}
+
+/// value type and memory access of a cell
+#[derive(Clone, Copy)]
+struct Type {
+    /// `i32` or `i64`
+    name: &'static str,
+    load: &'static str,
+    store: &'static str,
+}
+
+impl Type {
+    fn of(bits: u32) -> Type {
+        match bits {
+            8 => Type { name: "i32", load: "i32.load8_u", store: "i32.store8" },
+            16 => Type { name: "i32", load: "i32.load16_u", store: "i32.store16" },
+            32 => Type { name: "i32", load: "i32.load", store: "i32.store" },
+            _ => Type { name: "i64", load: "i64.load", store: "i64.store" },
+        }
+    }
+
+    fn load(self, tape: u64, at: &str) -> String {
+        format!("({} offset={tape} {at})", self.load)
+    }
+
+    fn store(self, tape: u64, at: &str, value: &str) -> String {
+        format!("({} offset={tape} {at} {value})", self.store)
+    }
+
+    fn extend(self, value: &str) -> String {
+        match self.name {
+            "i64" => format!("(i64.extend_i32_u {value})"),
+            _ => value.to_string(),
+        }
+    }
+
+    fn to_i32(self, value: &str) -> String {
+        match self.name {
+            "i64" => format!("(i32.wrap_i64 {value})"),
+            _ => value.to_string(),
+        }
+    }
+
+    fn to_i64(self, value: &str) -> String {
+        match self.name {
+            "i64" => value.to_string(),
+            _ => format!("(i64.extend_i32_u {value})"),
+        }
+    }
+}
+
+struct Gen<'a> {
+    f: String,
+    settings: &'a Settings,
+    source_name: &'a str,
+    ty: Type,
+    size: u64,
+    /// address of the tape
+    tape: u64,
+    /// the data pointer stays below this many bytes
+    end: u64,
+    /// reaching `end` is a bounds panic, otherwise the memory ran out
+    end_panics: bool,
+    r_msg: String,
+    /// output of partial evaluation, then messages at `DATA`
+    data: Vec<u8>,
+    /// addresses of messages in `data`
+    messages: HashMap<String, u64>,
+    /// indentation
+    depth: usize,
+    /// number of loops, for labels
+    loops: usize,
+    /// `$input` is used
+    reads: bool,
+    /// `$print_num` is used
+    prints: bool,
+    /// `$report` is used
+    panics: bool,
+    /// address of message of `$no_tape` when used
+    no_tape: Option<u64>,
+}
+
+impl<'a> Gen<'a> {
+    fn new(program: &Program, source_name: &'a str, settings: &'a Settings, tape: u64) -> Gen<'a> {
+        let size = (settings.cell_bits / 8) as u64;
+        let memory = (MEMORY - tape) / size * size;
+        let (end, end_panics) = match settings.tape_limit() {
+            Some(limit) if limit.saturating_mul(size) <= memory => (limit * size, true),
+            _ => (memory, false),
+        };
+        let mut g = Gen {
+            f: String::new(),
+            settings,
+            source_name,
+            ty: Type::of(settings.cell_bits),
+            size,
+            tape,
+            end,
+            end_panics,
+            r_msg: bounds_r_panic_msg(settings.tape_limit().unwrap_or(settings.cell_count)),
+            data: program.output.clone(),
+            messages: HashMap::new(),
+            depth: 2,
+            loops: 0,
+            reads: false,
+            prints: false,
+            panics: false,
+            no_tape: None,
+        };
+        if settings.tape == Tape::Growable {
+            g.no_tape = Some(g.message(NO_TAPE_MSG));
+        }
+        g
+    }
+
+    fn run(mut self, ops: &[Node]) -> Self {
+        self.block(ops);
+        self
+    }
+
+    fn line(&mut self, s: &str) {
+        write!(self.f, "{:1$}{s}\n", "", self.depth * 2);
+    }
+
+    /// address of `msg` in memory
+    fn message(&mut self, msg: &str) -> u64 {
+        if let Some(at) = self.messages.get(msg) {
+            return *at;
+        }
+        let at = DATA + self.data.len() as u64;
+        self.data.extend_from_slice(msg.as_bytes());
+        self.messages.insert(msg.to_string(), at);
+        at
+    }
+
+    /// call which reports `msg` at `span` and does not return
+    fn site(&mut self, msg: &str, span: Span, code: u8) -> String {
+        self.panics = true;
+        let msg = format!("\npanicked: {msg} | at {}:{}:{}\n", self.source_name, span.line, span.col);
+        let at = self.message(&msg);
+        format!("(call $report (i32.const {at}) (i32.const {}) (i32.const {code}))", msg.len())
+    }
+
+    fn cell(&self, at: &str) -> String {
+        self.ty.load(self.tape, at)
+    }
+
+    fn set_cell(&self, at: &str, value: &str) -> String {
+        self.ty.store(self.tape, at, value)
+    }
+
+    fn block(&mut self, ops: &[Node]) {
+        for node in ops {
+            self.process_op(node);
+        }
+    }
+
+    /// `var = pos + n` cells, bounds checked or wrapped, a growable tape grows to it
+    fn offset(&mut self, var: &str, n: isize, span: Span, in_bounds: bool) {
+        let size = self.size;
+        // a move this long always fails its check
+        let bytes = |m: u64| m.saturating_mul(size).min(u32::MAX as u64);
+        match Move::new(n, in_bounds, self.settings) {
+            Move::Wrap { k: 0, .. } => { self.line(&format!("(local.set {var} (local.get $pos))")); }
+            Move::Wrap { k, back } => {
+                let (k, back) = (k * size, back * size);
+                self.line(&format!("(local.set {var} (select (i32.sub (local.get $pos) (i32.const {back})) (i32.add (local.get $pos) (i32.const {k})) (i32.ge_u (local.get $pos) (i32.const {back}))))"));
+            }
+            Move::Right { m, limit, grow } => {
+                let m = bytes(m);
+                // growing without a limit still stops at the end of the memory
+                if limit.is_some() || grow {
+                    let fail = match self.end_panics {
+                        true => {
+                            let msg = self.r_msg.clone();
+                            self.site(&msg, span, PANIC_EXIT_CODE)
+                        }
+                        false => "(call $no_tape)".to_string(),
+                    };
+                    self.line(&format!("(if (i32.le_u (i32.sub (i32.const {}) (local.get $pos)) (i32.const {m})) (then {fail}))", self.end));
+                }
+                self.line(&format!("(local.set {var} (i32.add (local.get $pos) (i32.const {m})))"));
+                if grow {
+                    self.line(&format!("(if (i32.ge_u (local.get {var}) (global.get $len)) (then (call $grow (i32.add (local.get {var}) (i32.const {size})))))"));
+                }
+            }
+            Move::Left { m, check } => {
+                let m = bytes(m);
+                if check {
+                    let fail = self.site(BOUNDS_L_PANIC_MSG, span, PANIC_EXIT_CODE);
+                    self.line(&format!("(if (i32.lt_u (local.get $pos) (i32.const {m})) (then {fail}))"));
+                }
+                self.line(&format!("(local.set {var} (i32.sub (local.get $pos) (i32.const {m})))"));
+            }
+        }
+    }
+
+    /// `StepCheck` of a loop iteration in `$steps`
+    fn count_step(&mut self, span: Span) {
+        let Some(check) = StepCheck::new(self.settings) else { return; };
+        self.line("(local.set $steps (i64.add (local.get $steps) (i64.const 1)))");
+        if let Some(max) = check.max {
+            let fail = self.site(STEP_LIMIT_MSG, span, LIMIT_EXIT_CODE);
+            self.line(&format!("(if (i64.gt_u (local.get $steps) (i64.const {max})) (then {fail}))"));
+        }
+        if check.clock {
+            let fail = self.site(TIME_LIMIT_MSG, span, LIMIT_EXIT_CODE);
+            self.line(&format!("(if (i64.eqz (i64.and (local.get $steps) (i64.const {}))) (then (if (i64.gt_u (call $now) (local.get $deadline)) (then {fail}))))", CLOCK_INTERVAL - 1));
+        }
+    }
+
+    /// `(block (loop ...))` which runs while the current cell is not zero
+    fn while_nonzero(&mut self, span: Span, body: impl FnOnce(&mut Self)) {
+        self.loops += 1;
+        let i = self.loops;
+        let zero = format!("({}.eqz {})", self.ty.name, self.cell("(local.get $pos)"));
+        self.line(&format!("(block $b{i}"));
+        self.depth += 1;
+        self.line(&format!("(loop $l{i}"));
+        self.depth += 1;
+        self.line(&format!("(br_if $b{i} {zero})"));
+        self.count_step(span);
+        body(self);
+        self.line(&format!("(br $l{i})))"));
+        self.depth -= 2;
+    }
+
+    fn process_op(&mut self, node: &Node) {
+        let Node { op, span, in_bounds } = node;
+        let span = *span;
+        let mask = self.settings.cell_mask();
+        let ty = self.ty;
+        let cell = self.cell("(local.get $pos)");
+        match op {
+            &Op::Move(n) => { self.offset("$pos", n, span, *in_bounds); }
+            &Op::Add(n) => {
+                let m = n.unsigned_abs() as u64;
+                if self.settings.check_overflow {
+                    let fail = self.site(OVERFLOW_PANIC_MSG, span, PANIC_EXIT_CODE);
+                    match n {
+                        _ if m > mask => { self.line(&fail); }
+                        1.. => { self.line(&format!("(if ({}.gt_u {cell} ({}.const {})) (then {fail}))", ty.name, ty.name, mask - m)); }
+                        _ => { self.line(&format!("(if ({}.lt_u {cell} ({}.const {m})) (then {fail}))", ty.name, ty.name)); }
+                    }
+                }
+                let sum = format!("({}.add {cell} ({}.const {}))", ty.name, ty.name, n as i64 as u64 & mask);
+                let s = self.set_cell("(local.get $pos)", &sum);
+                self.line(&s);
+            }
+            Op::Out => { self.line(&format!("(call $putc {})", ty.to_i32(&cell))); }
+            Op::In => {
+                self.reads = true;
+                self.line("(call $input (local.get $pos))");
+            }
+            Op::PrintNum => {
+                self.prints = true;
+                self.line(&format!("(call $print_num {})", ty.to_i64(&cell)));
+            }
+            Op::SetZero => {
+                let s = self.set_cell("(local.get $pos)", &format!("({}.const 0)", ty.name));
+                self.line(&s);
+            }
+            Op::Loop(body) => { self.while_nonzero(span, |g| g.block(body)); }
+            &Op::Scan(n) => { self.while_nonzero(span, |g| g.offset("$pos", n, span, false)); }
+            &Op::MulAdd(offset, factor) => {
+                let nonzero = match ty.name {
+                    "i64" => format!("(i64.ne {cell} (i64.const 0))"),
+                    _ => cell.clone(),
+                };
+                self.line(&format!("(if {nonzero}"));
+                self.depth += 1;
+                self.line("(then");
+                self.depth += 1;
+                self.offset("$t", offset, span, *in_bounds);
+                let product = format!("({}.mul {cell} ({}.const {}))", ty.name, ty.name, factor as i64 as u64 & mask);
+                let sum = format!("({}.add {} {product})", ty.name, self.cell("(local.get $t)"));
+                let s = self.set_cell("(local.get $t)", &sum);
+                self.line(&format!("{s}))"));
+                self.depth -= 2;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wat(code: &str, settings: &Settings) -> String {
+        let mut program = Program::parse(code).expect("valid program");
+        crate::opt::optimize(&mut program, settings);
+        crate::opt::eliminate_bounds_checks(&mut program, settings);
+        emit(&program, "a.b", settings)
+    }
+
+    #[test]
+    fn tape_size_limit() {
+        for bits in [8, 16, 32, 64] {
+            let most = (MEMORY - RESERVED) / (bits / 8) as u64;
+            let settings = |cell_count| Settings { cell_count, cell_bits: bits, ..Default::default() };
+            assert!(check(&settings(most)).is_ok());
+            assert!(check(&settings(most + 1)).is_err());
+        }
+        assert!(check(&Settings { target: Some("wasm64-unknown-unknown".to_string()), ..Default::default() }).is_err());
+        // the largest tape still fits the 32-bit memory after everything else
+        let f = wat("+[>+]", &Settings { cell_count: MEMORY - RESERVED, ..Default::default() });
+        assert!(f.contains("(memory (export \"memory\") 65281)"), "{f}");
+    }
+
+    #[test]
+    fn growable_memory() {
+        let settings = Settings { cell_count: 100, cell_bits: 16, tape: Tape::Growable, ..Default::default() };
+        let f = wat("+[>+]", &settings);
+        assert!(f.contains("(memory (export \"memory\") 1)") && f.contains("(global $len (mut i32) (i32.const 200))"), "{f}");
+        // without `--max-cells` the tape grows until the memory runs out, which is not a bounds panic
+        assert!(f.contains("(func $grow") && f.contains("(call $no_tape)") && !f.contains("exceeded bounds check"), "{f}");
+        let tape: u64 = f.split("(i32.store16 offset=").nth(1).and_then(|r| r.split(' ').next()).and_then(|t| t.parse().ok()).expect("tape address");
+        assert!(f.contains(&format!("(i64.const {})", (MEMORY - tape) / 2 * 2)), "{f}");
+
+        let settings = Settings { max_cells: Some(70000), ..settings };
+        let f = wat("+[>+]", &settings);
+        assert!(f.contains("(i64.const 140000)") && f.contains("exceeded bounds check (larger than 70000)"), "{f}");
+
+        // the initial size never goes past `--max-cells`
+        let settings = Settings { cell_count: 8, cell_bits: 8, max_cells: Some(6), ..settings };
+        let f = wat(">>>>>>.", &settings);
+        assert!(f.contains("(global $len (mut i32) (i32.const 6))") && f.contains("exceeded bounds check (larger than 6)"), "{f}");
+    }
+
+    #[test]
+    fn eof() {
+        for (eof, bits, then) in [
+            (Eof::Zero, 8, "(local.get $at) (i32.const 0)) (return))"),
+            (Eof::MinusOne, 8, "(local.get $at) (i32.const 255)) (return))"),
+            (Eof::MinusOne, 16, "(local.get $at) (i32.const 65535)) (return))"),
+            (Eof::MinusOne, 64, "(local.get $at) (i64.const 18446744073709551615)) (return))"),
+            (Eof::Unchanged, 32, "(then (return)))"),
+        ] {
+            let f = wat(",.", &Settings { eof, cell_bits: bits, ..Default::default() });
+            // an error and reading nothing are both the end of input
+            let read = "(if (i32.or (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)) (i32.eqz (i32.load (i32.const 8))))";
+            assert!(f.contains(&format!("{read}\n      (then")) && f.contains(then), "{eof:?} {bits}\n{f}");
+        }
+        assert!(!wat("+.", &Settings::default()).contains("fd_read"));
+    }
+}
@@ -0,0 +1,567 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+
+//! lowering of `ir::Program` to x86-64 GNU assembler for Linux, the runtime uses only system calls
+//!
+//! the tape is mapped at start and its address is in `r12`, the data pointer is the cell index in `r13`,
+//! `r14` counts loop iterations and `r15` is the length of the output buffer
+
+use std::fmt::Write as _;
+use crate::codegen::{CLOCK_INTERVAL, Escape, Move, StepCheck, escape};
+use crate::error::CompileError;
+use crate::interpreter::{BOUNDS_L_PANIC_MSG, OVERFLOW_PANIC_MSG, STEP_LIMIT_MSG, TIME_LIMIT_MSG, bounds_r_panic_msg};
+use crate::interpreter::{LIMIT_EXIT_CODE, PANIC_EXIT_CODE};
+use crate::ir::{Node, Op, Program};
+use crate::settings::{Eof, Settings, Tape, Terminal};
+use crate::token::Span;
+
+/// bytes written at once
+const OUT_SIZE: usize = 4096;
+const NO_TAPE_MSG: &str = "failed to allocate tape\n";
+
+/// largest tape, half of the user address space
+pub(crate) const MAX_TAPE: u64 = 1 << 46;
+
+/// errors for settings the assembly backends cannot do
+pub fn check(settings: &Settings) -> Result<(), CompileError> {
+    let unsupported = |what: &str| Err(CompileError::Unsupported(format!("{what} is not supported by the x86-64 backends")));
+    if settings.target.as_deref().is_some_and(|t| !(t.starts_with("x86_64") && t.contains("linux"))) {
+        return unsupported("target other than x86_64 Linux");
+    }
+    if settings.tape == Tape::Growable {
+        return unsupported("--tape growable");
+    }
+    if settings.dump_on_panic || settings.dump_on_exit {
+        return unsupported("dumping tape");
+    }
+    let size = (settings.cell_bits / 8) as u64;
+    if settings.cell_count.checked_mul(size).is_none_or(|bytes| bytes > MAX_TAPE) {
+        return unsupported(&format!("--cell-count above {} with {}-bit cells", MAX_TAPE / size, settings.cell_bits));
+    }
+    Ok(())
+}
+
+/// generates GNU assembler source of whole program, `_start` is the entry point
+pub fn emit(program: &Program, source_name: &str, settings: &Settings) -> String {
+    let mut g = Gen {
+        f: String::new(),
+        settings,
+        source_name,
+        size: (settings.cell_bits / 8) as usize,
+        r_msg: bounds_r_panic_msg(settings.cell_count),
+        index: 0,
+        sites: vec![],
+    };
+    g.block(&program.ops);
+    let Gen { f: body, sites, size, .. } = g;
+
+    let mut f = String::new();
+    write!(f, "# {}\n", source_name.replace('\n', " "));
+    write!(f, "    .intel_syntax noprefix\n    .globl _start\n\n");
+
+    write!(f, "    .section .rodata\n");
+    if !program.output.is_empty() {
+        write!(f, "bf_prefix:\n    .ascii \"{}\"\n", escape(&program.output, Escape::Octal));
+    }
+    if !program.tape.is_empty() {
+        let directive = match size { 1 => ".byte", 2 => ".short", 4 => ".long", _ => ".quad" };
+        let init: Vec<String> = program.tape.iter().map(|v| v.to_string()).collect();
+        write!(f, "bf_init:\n    {directive} {}\n", init.join(", "));
+    }
+    for (i, (message, _)) in sites.iter().enumerate() {
+        write!(f, ".Lmsg{i}:\n    .ascii \"{}\"\n", escape(message.as_bytes(), Escape::Octal));
+    }
+    write!(f, "bf_no_tape_msg:\n    .ascii \"{}\"\n", escape(NO_TAPE_MSG.as_bytes(), Escape::Octal));
+
+    write!(f, "
+    .section .bss
+bf_out:
+    .skip {OUT_SIZE}
+bf_in:
+    .skip 8
+bf_num:
+    .skip 32
+bf_termios_old:
+    .skip 64
+bf_termios_new:
+    .skip 64
+bf_tty:
+    .skip 8
+bf_deadline:
+    .skip 8
+bf_timespec:
+    .skip 16
+");
+
+    write!(f, "\n    .text\n_start:\n");
+    write!(f, "    mov r13, {}\n    xor r14d, r14d\n    xor r15d, r15d\n", program.start);
+    // overcommit accounting refuses a large .bss, not a mapping with MAP_NORESERVE
+    write!(f, "    # mmap of PROT_READ | PROT_WRITE and MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE, zeroed tape
+    mov eax, 9
+    xor edi, edi
+    mov rsi, {}
+    mov edx, 3
+    mov r10d, 0x4022
+    mov r8, -1
+    xor r9d, r9d
+    syscall
+    test rax, rax
+    js bf_no_tape
+    mov r12, rax
+", settings.cell_count * size as u64);
+    if settings.terminal != Terminal::Untouched {
+        // ICANON | ECHO of c_lflag
+        let change = match settings.terminal {
+            Terminal::Cooked => "or dword ptr [rip + bf_termios_new + 12], 10",
+            _ => "and dword ptr [rip + bf_termios_new + 12], -11",
+        };
+        write!(f, "    # TCGETS fails when stdin is not a terminal, which is then left alone
+    mov eax, 16
+    xor edi, edi
+    mov esi, 0x5401
+    lea rdx, [rip + bf_termios_old]
+    syscall
+    test rax, rax
+    jnz .Lterminal_done
+    mov byte ptr [rip + bf_tty], 1
+    lea rsi, [rip + bf_termios_old]
+    lea rdi, [rip + bf_termios_new]
+    mov ecx, 64
+    rep movsb
+    {change}
+    mov eax, 16
+    xor edi, edi
+    mov esi, 0x5402
+    lea rdx, [rip + bf_termios_new]
+    syscall
+.Lterminal_done:
+");
+    }
+    if !program.tape.is_empty() {
+        write!(f, "    lea rsi, [rip + bf_init]\n    mov rdi, r12\n    mov ecx, {}\n    rep movsb\n", program.tape.len() * size);
+    }
+    if !program.output.is_empty() {
+        write!(f, "    lea rsi, [rip + bf_prefix]\n    mov rdx, {}\n    call bf_write\n", program.output.len());
+    }
+    if let Some(ms) = settings.timeout_ms {
+        write!(f, "    call bf_now\n    mov rcx, {}\n    add rax, rcx\n    mov [rip + bf_deadline], rax\n", ms.saturating_mul(1_000_000).min(i64::MAX as u64));
+    }
+    f += &body;
+    write!(f, "    xor edi, edi\n    jmp bf_exit\n");
+
+    for (i, (message, code)) in sites.iter().enumerate() {
+        write!(f, ".Lsite{i}:\n    lea rsi, [rip + .Lmsg{i}]\n    mov edx, {}\n    mov edi, {code}\n    jmp bf_report\n", message.len());
+    }
+    write_runtime(&mut f, settings, size);
+    f
+}
+
+/// `bf_write` (rsi, rdx), `bf_flush`, `bf_putc` (al), `bf_print_num` (rax), `bf_read`, `bf_now`,
+/// `bf_no_tape`, `bf_report` (rsi, rdx, edi) and `bf_exit` (edi), every one keeps r12 - r14
+fn write_runtime(f: &mut String, settings: &Settings, size: usize) {
+    write!(f, "
+# writes rdx bytes at rsi to stdout
+bf_write:
+    push rax
+    push rcx
+    push rdi
+    push r11
+.Lwrite_more:
+    test rdx, rdx
+    jz .Lwrite_done
+    mov eax, 1
+    mov edi, 1
+    syscall
+    test rax, rax
+    jle .Lwrite_done
+    add rsi, rax
+    sub rdx, rax
+    jmp .Lwrite_more
+.Lwrite_done:
+    pop r11
+    pop rdi
+    pop rcx
+    pop rax
+    ret
+
+bf_flush:
+    push rsi
+    push rdx
+    lea rsi, [rip + bf_out]
+    mov rdx, r15
+    call bf_write
+    xor r15d, r15d
+    pop rdx
+    pop rsi
+    ret
+
+bf_putc:
+    push rdx
+    lea rdx, [rip + bf_out]
+    mov [rdx + r15], al
+    pop rdx
+    inc r15
+    cmp r15, {OUT_SIZE}
+    jae bf_flush
+    ret
+
+# decimal rax and a new line
+bf_print_num:
+    push rcx
+    push rdx
+    push rdi
+    lea rdi, [rip + bf_num + 32]
+    mov ecx, 10
+.Lnum_digit:
+    xor edx, edx
+    div rcx
+    add dl, '0'
+    dec rdi
+    mov [rdi], dl
+    test rax, rax
+    jnz .Lnum_digit
+    lea rcx, [rip + bf_num + 32]
+.Lnum_copy:
+    mov al, [rdi]
+    call bf_putc
+    inc rdi
+    cmp rdi, rcx
+    jb .Lnum_copy
+    mov al, 10
+    call bf_putc
+    pop rdi
+    pop rdx
+    pop rcx
+    ret
+");
+    let reg = reg(size);
+    let cell = format!("{} ptr [r12 + r13*{size}]", ptr(size));
+    let nl = if settings.override_new_line_to_null { "    cmp eax, 10\n    jne .Lread_store\n    xor eax, eax\n" } else { "" };
+    let eof = match settings.eof {
+        Eof::Zero => format!("    xor eax, eax\n    mov {cell}, {reg}\n"),
+        Eof::MinusOne => format!("    mov rax, -1\n    mov {cell}, {reg}\n"),
+        Eof::Unchanged => String::new(),
+    };
+    write!(f, "
+# ',' with output flushed first
+bf_read:
+    call bf_flush
+    xor eax, eax
+    xor edi, edi
+    lea rsi, [rip + bf_in]
+    mov edx, 1
+    syscall
+    cmp rax, 1
+    jne .Lread_eof
+    movzx eax, byte ptr [rip + bf_in]
+{nl}.Lread_store:
+    mov {cell}, {reg}
+    ret
+.Lread_eof:
+{eof}    ret
+
+# nanoseconds of monotonic clock in rax
+bf_now:
+    push rcx
+    push rdx
+    push rdi
+    push rsi
+    push r11
+    mov eax, 228
+    mov edi, 1
+    lea rsi, [rip + bf_timespec]
+    syscall
+    mov rax, [rip + bf_timespec]
+    mov rcx, 1000000000
+    mul rcx
+    add rax, [rip + bf_timespec + 8]
+    pop r11
+    pop rsi
+    pop rdi
+    pop rdx
+    pop rcx
+    ret
+
+# the tape could not be mapped
+bf_no_tape:
+    lea rsi, [rip + bf_no_tape_msg]
+    mov edx, {}
+    mov edi, {PANIC_EXIT_CODE}
+
+# prints rdx bytes of message at rsi after the output and exits with edi
+bf_report:
+    push rdi
+    push rsi
+    push rdx
+    call bf_flush
+    pop rdx
+    pop rsi
+    call bf_write
+    pop rdi
+
+# flushes output, restores terminal and exits with edi
+bf_exit:
+    call bf_flush
+    cmp byte ptr [rip + bf_tty], 0
+    je .Lexit
+    push rdi
+    mov eax, 16
+    xor edi, edi
+    mov esi, 0x5402
+    lea rdx, [rip + bf_termios_old]
+    syscall
+    pop rdi
+.Lexit:
+    mov eax, 60
+    syscall
+", NO_TAPE_MSG.len());
+}
+
+/// size keyword of `size` bytes memory operand
+fn ptr(size: usize) -> &'static str {
+    match size { 1 => "byte", 2 => "word", 4 => "dword", _ => "qword" }
+}
+
+/// part of rax with `size` bytes
+fn reg(size: usize) -> &'static str {
+    match size { 1 => "al", 2 => "ax", 4 => "eax", _ => "rax" }
+}
+
+struct Gen<'a> {
+    f: String,
+    settings: &'a Settings,
+    source_name: &'a str,
+    /// bytes of a cell
+    size: usize,
+    r_msg: String,
+    /// makes labels unique
+    index: usize,
+    /// (message, exit code) of every place which stops the program, jumped to as `.Lsite{i}`
+    sites: Vec<(String, u8)>,
+}
+
+impl Gen<'_> {
+    fn next(&mut self) -> usize {
+        self.index += 1;
+        self.index
+    }
+
+    /// label of a new place reporting `msg` at `span`
+    fn site(&mut self, msg: &str, span: Span, code: u8) -> String {
+        self.sites.push((format!("\npanicked: {msg} | at {}:{}:{}\n", self.source_name, span.line, span.col), code));
+        format!(".Lsite{}", self.sites.len() - 1)
+    }
+
+    /// `v` as an operand, through `reg` when it does not fit 32-bit immediate
+    fn imm(&mut self, v: i64, reg: &str) -> String {
+        if i32::try_from(v).is_ok() {
+            v.to_string()
+        } else {
+            write!(self.f, "    mov {reg}, {v}\n");
+            reg.to_string()
+        }
+    }
+
+    /// memory operand of the cell at index in `index`
+    fn cell(&self, index: &str) -> String {
+        format!("{} ptr [r12 + {index}*{}]", ptr(self.size), self.size)
+    }
+
+    /// `n` as a signed immediate of cell width which wraps the same way
+    fn cell_imm(&self, n: i64) -> i64 {
+        let shift = 64 - self.settings.cell_bits;
+        (n << shift) >> shift
+    }
+
+    fn block(&mut self, ops: &[Node]) {
+        for node in ops {
+            self.process_op(node);
+        }
+    }
+
+    /// `index` = r13 + `n`, bounds checked or wrapped
+    fn offset(&mut self, index: &str, n: isize, span: Span, in_bounds: bool) {
+        if index != "r13" {
+            write!(self.f, "    mov {index}, r13\n");
+        }
+        match Move::new(n, in_bounds, self.settings) {
+            Move::Wrap { k: 0, .. } => {}
+            Move::Wrap { back, .. } => {
+                // borrow means pos + k is still on the tape
+                let back = self.imm(back as i64, "rax");
+                write!(self.f, "    sub {index}, {back}\n");
+                let c = self.imm(self.settings.cell_count as i64, "rax");
+                let i = self.next();
+                write!(self.f, "    jae .Lwrapped{i}\n    add {index}, {c}\n.Lwrapped{i}:\n");
+            }
+            Move::Right { m, limit, .. } => {
+                if let Some(limit) = limit {
+                    let site = self.site(&self.r_msg.clone(), span, PANIC_EXIT_CODE);
+                    // pos + m >= limit without overflow, compared unsigned
+                    match limit.checked_sub(m).filter(|r| *r > 0) {
+                        Some(room) => {
+                            let room = self.imm(room as i64, "rax");
+                            write!(self.f, "    cmp r13, {room}\n    jae {site}\n");
+                        }
+                        None => { write!(self.f, "    jmp {site}\n"); }
+                    }
+                }
+                if m != 0 {
+                    let m = self.imm(m as i64, "rax");
+                    write!(self.f, "    add {index}, {m}\n");
+                }
+            }
+            Move::Left { m, check } => {
+                if check {
+                    let site = self.site(BOUNDS_L_PANIC_MSG, span, PANIC_EXIT_CODE);
+                    let m = self.imm(m as i64, "rax");
+                    write!(self.f, "    cmp r13, {m}\n    jb {site}\n");
+                }
+                let m = self.imm(m as i64, "rax");
+                write!(self.f, "    sub {index}, {m}\n");
+            }
+        }
+    }
+
+    /// `StepCheck` of a loop iteration in r14
+    fn count_step(&mut self, span: Span) {
+        let Some(check) = StepCheck::new(self.settings) else { return; };
+        write!(self.f, "    inc r14\n");
+        if let Some(max) = check.max {
+            let site = self.site(STEP_LIMIT_MSG, span, LIMIT_EXIT_CODE);
+            let max = self.imm(max.min(i64::MAX as u64) as i64, "rax");
+            write!(self.f, "    cmp r14, {max}\n    ja {site}\n");
+        }
+        if check.clock {
+            let site = self.site(TIME_LIMIT_MSG, span, LIMIT_EXIT_CODE);
+            let i = self.next();
+            write!(self.f, "    test r14d, {}\n    jnz .Lclock{i}\n    call bf_now\n    cmp rax, [rip + bf_deadline]\n    jg {site}\n.Lclock{i}:\n", CLOCK_INTERVAL - 1);
+        }
+    }
+
+    fn process_op(&mut self, node: &Node) {
+        let Node { op, span, in_bounds } = node;
+        let span = *span;
+        let cell = self.cell("r13");
+        match op {
+            &Op::Move(n) => { self.offset("r13", n, span, *in_bounds); }
+            &Op::Add(n) => {
+                let m = n.unsigned_abs() as u64;
+                let v = self.cell_imm(m as i64);
+                if !self.settings.check_overflow {
+                    write!(self.f, "    add {cell}, {}\n", self.cell_imm(n as i64));
+                } else if m > self.settings.cell_mask() {
+                    let site = self.site(OVERFLOW_PANIC_MSG, span, PANIC_EXIT_CODE);
+                    write!(self.f, "    jmp {site}\n");
+                } else {
+                    // carry is the unsigned overflow, the cell is not looked at after it
+                    let site = self.site(OVERFLOW_PANIC_MSG, span, PANIC_EXIT_CODE);
+                    let op = if n > 0 { "add" } else { "sub" };
+                    write!(self.f, "    {op} {cell}, {v}\n    jc {site}\n");
+                }
+            }
+            Op::Out => { write!(self.f, "    mov al, byte ptr [r12 + r13*{}]\n    call bf_putc\n", self.size); }
+            Op::In => { write!(self.f, "    call bf_read\n"); }
+            Op::PrintNum => { write!(self.f, "{}    call bf_print_num\n", self.load("r13")); }
+            Op::SetZero => { write!(self.f, "    mov {cell}, 0\n"); }
+            Op::Loop(body) => {
+                let i = self.next();
+                write!(self.f, ".Lloop{i}:\n    cmp {cell}, 0\n    je .Lloop_end{i}\n");
+                self.count_step(span);
+                self.block(body);
+                write!(self.f, "    jmp .Lloop{i}\n.Lloop_end{i}:\n");
+            }
+            &Op::Scan(n) => {
+                let i = self.next();
+                write!(self.f, ".Lscan{i}:\n    cmp {cell}, 0\n    je .Lscan_end{i}\n");
+                self.count_step(span);
+                self.offset("r13", n, span, false);
+                write!(self.f, "    jmp .Lscan{i}\n.Lscan_end{i}:\n");
+            }
+            &Op::MulAdd(offset, factor) => {
+                let i = self.next();
+                write!(self.f, "    cmp {cell}, 0\n    je .Lmul_end{i}\n");
+                self.offset("rdx", offset, span, *in_bounds);
+                let target = self.cell("rdx");
+                write!(self.f, "{}    imul rax, rax, {factor}\n    add {target}, {}\n.Lmul_end{i}:\n", self.load("r13"), reg(self.size));
+            }
+        }
+    }
+
+    /// loads the cell at index in `index` to rax, zero extended
+    fn load(&self, index: &str) -> String {
+        let cell = self.cell(index);
+        match self.size {
+            1 | 2 => format!("    movzx eax, {cell}\n"),
+            4 => format!("    mov eax, {cell}\n"),
+            _ => format!("    mov rax, {cell}\n"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asm(code: &str, settings: &Settings) -> String {
+        let mut program = Program::parse(code).expect("valid program");
+        crate::opt::optimize(&mut program, settings);
+        crate::opt::eliminate_bounds_checks(&mut program, settings);
+        emit(&program, "a.b", settings)
+    }
+
+    #[test]
+    fn tape_size_limit() {
+        let settings = |cell_count, cell_bits| Settings { cell_count, cell_bits, ..Default::default() };
+        assert!(check(&settings(MAX_TAPE, 8)).is_ok());
+        assert!(check(&settings(MAX_TAPE / 8, 64)).is_ok());
+        assert!(check(&settings(MAX_TAPE + 1, 8)).is_err());
+        assert!(check(&settings(MAX_TAPE / 2 + 1, 16)).is_err());
+        assert!(check(&settings(1 << 63, 16)).is_err());
+        assert!(check(&settings(u64::MAX, 8)).is_err());
+    }
+
+    #[test]
+    fn largest_tape() {
+        // room which does not fit 32-bit immediate goes through rax
+        let settings = Settings { cell_count: MAX_TAPE, ..Default::default() };
+        let f = asm("+[>+]", &settings);
+        assert!(f.contains(&format!("    mov rax, {}\n    cmp r13, rax\n    jae .Lsite0\n", MAX_TAPE - 1)), "{f}");
+        assert!(!f.contains("jmp .Lsite"), "{f}");
+        let settings = Settings { cell_count: MAX_TAPE / 8, cell_bits: 64, ..Default::default() };
+        assert!(asm("+[>+]", &settings).contains(&format!("    mov rax, {}\n    cmp r13, rax\n", MAX_TAPE / 8 - 1)));
+    }
+
+    #[test]
+    fn tape_is_mapped() {
+        let settings = Settings { cell_count: MAX_TAPE / 8, cell_bits: 64, ..Default::default() };
+        let f = asm("+", &settings);
+        assert!(f.contains(&format!("    mov rsi, {MAX_TAPE}\n    mov edx, 3\n    mov r10d, 0x4022\n")), "{f}");
+        assert!(f.contains("    syscall\n    test rax, rax\n    js bf_no_tape\n    mov r12, rax\n"), "{f}");
+        assert!(!f.contains(".skip 70368744177664"), "{f}");
+        let f = asm("+", &Settings { cell_count: 3, cell_bits: 16, ..Default::default() });
+        assert!(f.contains("    mov rsi, 6\n"), "{f}");
+    }
+
+    #[test]
+    fn move_past_the_tape_always_panics() {
+        let settings = Settings { cell_count: 3, ..Default::default() };
+        let f = asm(",[>>>]", &settings);
+        assert!(f.contains("    jmp .Lsite0\n"), "{f}");
+    }
+}
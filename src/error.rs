@@ -0,0 +1,94 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use crate::token::Span;
+
+/// location of an error in source together with the text of its line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceError {
+    pub span: Span,
+    pub line_text: String,
+    pub file: Option<String>,
+}
+
+impl SourceError {
+    pub fn new(input: &str, span: Span) -> Self {
+        let line_text = input.lines().nth(span.line - 1).unwrap_or("").to_string();
+        SourceError { span, line_text, file: None }
+    }
+}
+
+#[derive(Debug)]
+pub enum CompileError {
+    /// '[' without matching ']'
+    UnmatchedOpen(SourceError),
+    /// ']' without matching '['
+    UnmatchedClose(SourceError),
+    UnreadableFile { path: PathBuf, error: std::io::Error },
+    /// compiler or assembler of emitted code is not installed
+    ToolNotFound(String),
+    /// tool failed with this stderr
+    Tool { name: String, stderr: String },
+    IrWrite { path: PathBuf, error: std::io::Error },
+    /// settings the chosen target or backend cannot do
+    Unsupported(String),
+    /// settings which contradict each other
+    Conflict(String),
+}
+
+impl CompileError {
+    /// sets file name shown in rendered error
+    pub fn with_file(mut self, name: &str) -> Self {
+        if let CompileError::UnmatchedOpen(e) | CompileError::UnmatchedClose(e) = &mut self {
+            e.file = Some(name.to_string());
+        }
+        self
+    }
+}
+
+fn render_source(f: &mut Formatter<'_>, msg: &str, e: &SourceError) -> std::fmt::Result {
+    let Span { line, col, .. } = e.span;
+    let w = line.to_string().len();
+    writeln!(f, "error: {msg}")?;
+    match &e.file {
+        Some(file) => writeln!(f, "{:w$}--> {file}:{line}:{col}", "")?,
+        None => writeln!(f, "{:w$}--> {line}:{col}", "")?,
+    }
+    writeln!(f, "{:w$} |", "")?;
+    writeln!(f, "{line} | {}", e.line_text)?;
+    // tabs are kept so that the caret lines up with the source line
+    let pad: String = e.line_text.chars().take(col - 1).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    std::write!(f, "{:w$} | {pad}^", "")
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::UnmatchedOpen(e) => render_source(f, "unmatched `[`, loop is never closed", e),
+            CompileError::UnmatchedClose(e) => render_source(f, "unmatched `]`, no loop to close", e),
+            CompileError::UnreadableFile { path, error } => std::write!(f, "error: cannot read file '{}': {error}", path.display()),
+            CompileError::ToolNotFound(name) => std::write!(f, "error: requires '{name}'"),
+            CompileError::Tool { name, stderr } => std::write!(f, "error: {name} failed with:\n{stderr}"),
+            CompileError::IrWrite { path, error } => std::write!(f, "error: cannot write emitted code to '{}': {error}", path.display()),
+            CompileError::Unsupported(msg) | CompileError::Conflict(msg) => std::write!(f, "error: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
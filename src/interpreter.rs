@@ -0,0 +1,415 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+use crate::codegen::CLOCK_INTERVAL;
+use crate::ir::{Node, Op, Program};
+use crate::settings::{Eof, Settings, Tape};
+use crate::token::Span;
+
+/// runtime error of interpreted program, mirrors `panic` block of generated code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Panic {
+    pub message: String,
+    pub span: Span,
+    /// `PANIC_EXIT_CODE` or `LIMIT_EXIT_CODE`
+    pub exit_code: u8,
+    pub file: Option<String>,
+}
+
+impl Panic {
+    fn new(message: impl Into<String>, span: Span, exit_code: u8) -> Self {
+        Panic { message: message.into(), span, exit_code, file: None }
+    }
+
+    /// sets file name shown before line and column
+    pub fn with_file(mut self, name: &str) -> Self {
+        self.file = Some(name.to_string());
+        self
+    }
+}
+
+impl Display for Panic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::write!(f, "panicked: {} | at ", self.message)?;
+        if let Some(file) = &self.file {
+            std::write!(f, "{file}:")?;
+        }
+        std::write!(f, "{}:{}", self.span.line, self.span.col)
+    }
+}
+
+impl std::error::Error for Panic {}
+
+pub fn bounds_r_panic_msg(cell_count: impl Display) -> String {
+    format!("exceeded bounds check (larger than {cell_count})")
+}
+
+pub const BOUNDS_L_PANIC_MSG: &str = "exceeded bounds check (smaller than 0)";
+
+pub const OVERFLOW_PANIC_MSG: &str = "cell overflow";
+
+pub const STEP_LIMIT_MSG: &str = "step limit exceeded";
+
+pub const TIME_LIMIT_MSG: &str = "time limit exceeded";
+
+pub const PANIC_EXIT_CODE: u8 = 1;
+
+/// exit code when `Settings::max_steps` or `Settings::timeout_ms` stops the program
+pub const LIMIT_EXIT_CODE: u8 = 2;
+
+/// why execution stopped early
+pub(crate) enum Stop {
+    Panic(Panic),
+    OutOfSteps,
+}
+
+impl From<Panic> for Stop {
+    fn from(p: Panic) -> Self {
+        Stop::Panic(p)
+    }
+}
+
+pub(crate) struct State<'a, R: Read, W: Write> {
+    pub(crate) settings: &'a Settings,
+    pub(crate) tape: Vec<u64>,
+    /// cells generated code would have allocated by now, `tape` grows only as they are touched
+    len: u64,
+    /// `Settings::cell_mask`
+    mask: u64,
+    pub(crate) pos: usize,
+    pub(crate) input: R,
+    pub(crate) output: W,
+    /// how many more ops and loop iterations can run
+    pub(crate) steps: usize,
+    /// how many more loop iterations `Settings::max_steps` allows
+    iterations: u64,
+    /// end of `Settings::timeout_ms`
+    pub(crate) deadline: Option<Instant>,
+}
+
+impl<'a, R: Read, W: Write> State<'a, R, W> {
+    /// state at start of the program
+    pub(crate) fn new(program: &Program, settings: &'a Settings, input: R, mut output: W) -> Self {
+        let mut tape = program.tape.clone();
+        tape.resize(tape.len().max(program.start + 1), 0);
+        _ = output.write_all(&program.output);
+        State { settings, tape, len: settings.initial_cells(program), mask: settings.cell_mask(), pos: program.start, input, output,
+            steps: usize::MAX, iterations: settings.max_steps.unwrap_or(u64::MAX), deadline: None }
+    }
+
+    fn step(&mut self) -> Result<(), Stop> {
+        self.steps = self.steps.checked_sub(1).ok_or(Stop::OutOfSteps)?;
+        Ok(())
+    }
+
+    /// `StepCheck` of a loop iteration, `iterations` is what remains of `Settings::max_steps`
+    fn iterate(&mut self, span: Span) -> Result<(), Stop> {
+        self.step()?;
+        let limit = |message: &str| Panic::new(message, span, LIMIT_EXIT_CODE);
+        self.iterations = self.iterations.checked_sub(1).ok_or_else(|| limit(STEP_LIMIT_MSG))?;
+        if let Some(deadline) = self.deadline && self.iterations.is_multiple_of(CLOCK_INTERVAL) && Instant::now() > deadline {
+            return Err(limit(TIME_LIMIT_MSG).into());
+        }
+        Ok(())
+    }
+
+    /// bounds checked or wrapped `pos + n`, the tape grows as cells are touched
+    fn offset(&mut self, n: isize, span: Span) -> Result<usize, Panic> {
+        let limit = self.settings.tape_limit().unwrap_or(u64::MAX);
+        let p = match self.pos.checked_add_signed(n) {
+            _ if self.settings.tape == Tape::Wrap => { (self.pos as i128 + n as i128).rem_euclid(limit as i128) as usize }
+            None => { return Err(Panic::new(BOUNDS_L_PANIC_MSG, span, PANIC_EXIT_CODE)); }
+            Some(p) if p as u64 >= limit => { return Err(Panic::new(bounds_r_panic_msg(limit), span, PANIC_EXIT_CODE)); }
+            Some(p) => { p }
+        };
+        if p >= self.tape.len() {
+            self.tape.resize((p + 1).max(self.tape.len() * 2).min(limit as usize), 0);
+        }
+        // a growable tape doubles the way generated code does it
+        if p as u64 >= self.len {
+            self.len = (p as u64 + 1).max(self.len.saturating_mul(2)).min(limit);
+        }
+        Ok(p)
+    }
+
+    /// prints data pointer and a window of `Settings::dump_width` cells around it to `err` the way generated code does,
+    /// writes whole tape to `Settings::dump_file`
+    fn dump(&self, err: &mut impl Write) {
+        let settings = self.settings;
+        let len = self.len;
+        let pos = self.pos as u64;
+        let start = pos.saturating_sub(settings.dump_width / 2);
+        let end = start.saturating_add(settings.dump_width).min(len);
+        let digits = settings.cell_bits as usize / 4;
+        _ = std::write!(err, "\ntape dump, pointer at {pos}\ncells {start}..{end}:");
+        for k in start..end {
+            let v = self.tape.get(k as usize).copied().unwrap_or(0);
+            _ = match k == pos {
+                true => std::write!(err, " [{v:0digits$x}]"),
+                false => std::write!(err, " {v:0digits$x}"),
+            };
+        }
+        _ = writeln!(err);
+        if let Some(path) = &settings.dump_file {
+            let bytes = settings.cell_bits as usize / 8;
+            let mut data: Vec<u8> = self.tape.iter().flat_map(|c| c.to_le_bytes()[..bytes].to_vec()).collect();
+            data.resize(len as usize * bytes, 0);
+            if std::fs::write(path, data).is_err() {
+                _ = writeln!(err, "failed to write tape to {}", path.display());
+            }
+        }
+    }
+
+    pub(crate) fn exec(&mut self, ops: &[Node]) -> Result<(), Stop> {
+        for Node { op, span, .. } in ops {
+            let span = *span;
+            self.step()?;
+            match op {
+                &Op::Move(n) => { self.pos = self.offset(n, span)?; }
+                &Op::Add(n) if self.settings.check_overflow => {
+                    let v = self.tape[self.pos] as i128 + n as i128;
+                    if v < 0 || v > self.mask as i128 {
+                        return Err(Panic::new(OVERFLOW_PANIC_MSG, span, PANIC_EXIT_CODE).into());
+                    }
+                    self.tape[self.pos] = v as u64;
+                }
+                &Op::Add(n) => { self.tape[self.pos] = self.tape[self.pos].wrapping_add(n as i64 as u64) & self.mask; }
+                // putchar writes lowest byte
+                Op::Out => { _ = self.output.write_all(&[self.tape[self.pos] as u8]); }
+                Op::In => {
+                    _ = self.output.flush();
+                    let mut b = [0u8];
+                    self.tape[self.pos] = match self.input.read(&mut b) {
+                        Ok(1) if self.settings.override_new_line_to_null && b[0] == b'\n' => { 0 }
+                        Ok(1) => { b[0] as u64 }
+                        _ => match self.settings.eof {
+                            Eof::Zero => { 0 }
+                            Eof::MinusOne => { self.mask }
+                            Eof::Unchanged => { self.tape[self.pos] }
+                        },
+                    };
+                }
+                Op::Loop(body) => {
+                    while self.tape[self.pos] != 0 {
+                        self.iterate(span)?;
+                        self.exec(body)?;
+                    }
+                }
+                Op::PrintNum => { _ = writeln!(self.output, "{}", self.tape[self.pos]); }
+                Op::SetZero => { self.tape[self.pos] = 0; }
+                &Op::Scan(n) => {
+                    while self.tape[self.pos] != 0 {
+                        self.iterate(span)?;
+                        self.pos = self.offset(n, span)?;
+                    }
+                }
+                &Op::MulAdd(offset, factor) => {
+                    let c = self.tape[self.pos];
+                    if c != 0 {
+                        let target = self.offset(offset, span)?;
+                        self.tape[target] = self.tape[target].wrapping_add(c.wrapping_mul(factor as i64 as u64)) & self.mask;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// executes program, output is flushed before every read
+pub fn run(program: &Program, settings: &Settings, input: &mut impl Read, output: &mut impl Write) -> Result<(), Panic> {
+    let mut state = State::new(program, settings, input, output);
+    state.deadline = settings.timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms));
+    let r = state.exec(&program.ops);
+    _ = state.output.flush();
+    let dump = match r {
+        Ok(()) => settings.dump_on_exit,
+        Err(_) => settings.dump_on_panic,
+    };
+    if dump {
+        state.dump(&mut std::io::stderr().lock());
+    }
+    match r {
+        Ok(()) => Ok(()),
+        Err(Stop::Panic(p)) => Err(p),
+        Err(Stop::OutOfSteps) => unreachable!("run has no step limit"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// output and result of running unoptimized `code`
+    fn exec(code: &str, settings: &Settings, input: &[u8]) -> (String, Result<(), Panic>) {
+        let program = Program::parse(code).expect("valid program");
+        let mut output = vec![];
+        let r = run(&program, settings, &mut &input[..], &mut output);
+        (String::from_utf8_lossy(&output).to_string(), r)
+    }
+
+    fn settings() -> Settings {
+        Settings { cell_count: 4, ..Default::default() }
+    }
+
+    #[test]
+    fn right_bounds_panic() {
+        let (out, r) = exec("+!\n >>>>", &settings(), b"");
+        assert_eq!(out, "1\n");
+        let p = r.unwrap_err();
+        assert_eq!(p.message, bounds_r_panic_msg(4));
+        assert_eq!((p.span.line, p.span.col), (2, 2));
+        assert_eq!(p.exit_code, PANIC_EXIT_CODE);
+        assert_eq!(p.with_file("a.b").to_string(), "panicked: exceeded bounds check (larger than 4) | at a.b:2:2");
+    }
+
+    #[test]
+    fn left_bounds_panic() {
+        let (_, r) = exec(">\n\n<<", &settings(), b"");
+        let p = r.unwrap_err();
+        assert_eq!(p.message, BOUNDS_L_PANIC_MSG);
+        assert_eq!((p.span.line, p.span.col), (3, 1));
+        assert_eq!(p.exit_code, PANIC_EXIT_CODE);
+    }
+
+    #[test]
+    fn eof() {
+        for (eof, expected) in [(Eof::Zero, "0\n"), (Eof::MinusOne, "255\n"), (Eof::Unchanged, "3\n")] {
+            let settings = Settings { eof, ..settings() };
+            assert_eq!(exec("+++,!", &settings, b""), (expected.to_string(), Ok(())));
+            assert_eq!(exec("+++,!", &settings, b"A"), ("65\n".to_string(), Ok(())));
+        }
+        let settings = Settings { cell_bits: 16, eof: Eof::MinusOne, ..settings() };
+        assert_eq!(exec(",!", &settings, b"").0, "65535\n");
+    }
+
+    #[test]
+    fn new_line_as_null() {
+        let settings = Settings { override_new_line_to_null: true, ..settings() };
+        assert_eq!(exec(",!,!", &settings, b"\na").0, "0\n97\n");
+    }
+
+    #[test]
+    fn cell_bits_wrap() {
+        for (bits, max) in [(8, "255"), (16, "65535"), (32, "4294967295"), (64, "18446744073709551615")] {
+            let settings = Settings { cell_bits: bits, ..settings() };
+            assert_eq!(exec("-!+!", &settings, b""), (format!("{max}\n0\n"), Ok(())));
+        }
+    }
+
+    #[test]
+    fn overflow_check() {
+        let settings = Settings { check_overflow: true, ..settings() };
+        let (out, r) = exec("+!--", &settings, b"");
+        assert_eq!(out, "1\n");
+        let p = r.unwrap_err();
+        assert_eq!((p.message.as_str(), p.span.col), (OVERFLOW_PANIC_MSG, 3));
+    }
+
+    #[test]
+    fn growable_tape() {
+        let settings = Settings { cell_count: 1, tape: Tape::Growable, ..settings() };
+        assert_eq!(exec(">>>>>>>>>>+!", &settings, b""), ("1\n".to_string(), Ok(())));
+
+        let settings = Settings { max_cells: Some(3), ..settings };
+        let p = exec(">>+>+", &settings, b"").1.unwrap_err();
+        assert_eq!((p.message, p.span.col), (bounds_r_panic_msg(3), 4));
+    }
+
+    #[test]
+    fn wrap_tape() {
+        let settings = Settings { cell_count: 3, tape: Tape::Wrap, ..settings() };
+        // 0 - 1 is the last cell, 2 + 3 is the same cell again
+        assert_eq!(exec("<+>>>!", &settings, b""), ("1\n".to_string(), Ok(())));
+        assert_eq!(exec(">>>+<<<!", &settings, b""), ("1\n".to_string(), Ok(())));
+    }
+
+    /// line and column of the panic of `code` run after optimizations and partial evaluation
+    fn panic_at(code: &str, settings: &Settings) -> (usize, usize) {
+        let mut program = Program::parse(code).expect("valid program");
+        crate::opt::optimize(&mut program, settings);
+        crate::opt::partial_eval(&mut program, settings);
+        let p = run(&program, settings, &mut &b""[..], &mut vec![]).unwrap_err();
+        (p.span.line, p.span.col)
+    }
+
+    #[test]
+    fn panic_locations() {
+        // carriage return is a column of its line, the last line has no new line
+        assert_eq!(panic_at("+\r\n+\r\n ,>>>>", &settings()), (3, 3));
+        assert_eq!(panic_at("+\n\n\t,<", &settings()), (3, 3));
+        // opposing moves fold into the first of them
+        assert_eq!(panic_at(",\n>>\n<<<<<", &settings()), (2, 1));
+        // partial evaluation rolls back to the op which panics
+        assert_eq!(panic_at("+[>+]", &settings()), (1, 3));
+        assert_eq!(panic_at("++\r\n>>+\r\n>>>", &settings()), (3, 1));
+        // a folded op panics at its first token even when a later one overflows
+        let settings = Settings { check_overflow: true, ..settings() };
+        assert_eq!(panic_at("+\n-\n--", &settings), (2, 1));
+        assert_eq!(panic_at("+\n-+\n--", &settings), (3, 1));
+    }
+
+    /// tape dump of `code` and the file it wrote
+    fn dump(code: &str, settings: &Settings) -> (String, Vec<u8>) {
+        let path = std::env::temp_dir().join(format!("bf_dump_test_{}_{}", std::process::id(), code.len()));
+        let settings = Settings { dump_file: Some(path.clone()), ..settings.clone() };
+        let program = Program::parse(code).expect("valid program");
+        let mut state = State::new(&program, &settings, &b""[..], vec![]);
+        _ = state.exec(&program.ops);
+        let mut err = vec![];
+        state.dump(&mut err);
+        let file = std::fs::read(&path).expect("dump file");
+        _ = std::fs::remove_file(&path);
+        (String::from_utf8(err).expect("text"), file)
+    }
+
+    #[test]
+    fn dumps() {
+        let settings = Settings { cell_count: 12, cell_bits: 16, dump_width: 5, ..Default::default() };
+        let (text, file) = dump("+>++>+++", &settings);
+        assert_eq!(text, "\ntape dump, pointer at 2\ncells 0..5: 0001 0002 [0003] 0000 0000\n");
+        assert_eq!(file.len(), 24);
+        assert_eq!(file[..6], [1, 0, 2, 0, 3, 0]);
+        // the window stops at the end of the tape
+        assert_eq!(dump(">>>>>>>>>>>-", &settings).0, "\ntape dump, pointer at 11\ncells 9..12: 0000 0000 [ffff]\n");
+
+        // a growable tape is as long as generated code would have allocated, not as far as the pointer went
+        let settings = Settings { tape: Tape::Growable, ..settings };
+        let (text, file) = dump("+>++>+++", &settings);
+        assert_eq!(text, "\ntape dump, pointer at 2\ncells 0..5: 0001 0002 [0003] 0000 0000\n");
+        assert_eq!(file.len(), 24);
+        let settings = Settings { dump_width: 40, ..settings };
+        let (text, file) = dump(">>>>>>>>>>>>>+", &settings);
+        assert!(text.starts_with("\ntape dump, pointer at 13\ncells 0..24:"), "{text}");
+        assert_eq!(file.len(), 48);
+        let settings = Settings { max_cells: Some(20), ..settings };
+        assert_eq!(dump(">>>>>>>>>>>>>+", &settings).1.len(), 40);
+    }
+
+    #[test]
+    fn step_limit() {
+        let settings = Settings { max_steps: Some(10), ..settings() };
+        let (out, r) = exec("+[.]", &settings, b"");
+        assert_eq!(out, "\u{1}".repeat(10));
+        let p = r.unwrap_err();
+        assert_eq!((p.message.as_str(), p.exit_code), (STEP_LIMIT_MSG, LIMIT_EXIT_CODE));
+        assert_eq!((p.span.line, p.span.col), (1, 2));
+    }
+}
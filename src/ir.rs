@@ -0,0 +1,175 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use crate::error::{CompileError, SourceError};
+use crate::token::{Span, Token, tokenize};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// adds to the current cell, wrapping
+    Add(i32),
+    /// moves the data pointer, positive is right
+    Move(isize),
+    /// `[...]`, runs body while the current cell is not zero
+    Loop(Vec<Node>),
+    Out,
+    In,
+    PrintNum,
+    /// sets the current cell to 0
+    SetZero,
+    /// `cell[offset] += cell * factor`, bounds are checked only when the current cell is not 0
+    MulAdd(isize, i32),
+    /// `[>]` like loop, moves by stride until the current cell is 0
+    Scan(isize),
+}
+
+/// operation with position of the (first) token it was made of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub op: Op,
+    pub span: Span,
+    /// op provably stays on the tape and needs no bounds check, see `opt::eliminate_bounds_checks`
+    pub in_bounds: bool,
+}
+
+impl Node {
+    pub fn new(op: Op, span: Span) -> Self {
+        Node { op, span, in_bounds: false }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program {
+    pub ops: Vec<Node>,
+    /// printed before `ops` run, see `opt::partial_eval`
+    pub output: Vec<u8>,
+    /// initial values of first cells, the rest is 0
+    pub tape: Vec<u64>,
+    /// initial position of the data pointer
+    pub start: usize,
+    /// char index at which each line of the source starts, maps `Span::pos` to line and column at runtime
+    pub line_starts: Vec<usize>,
+}
+
+impl Program {
+    /// builds loop tree and merges runs of the same token, brackets are validated here
+    pub fn parse(input: &str) -> Result<Self, CompileError> {
+        // (ops of enclosing block, position of '[')
+        let mut stack: Vec<(Vec<Node>, Span)> = vec![];
+        let mut ops: Vec<Node> = vec![];
+        let mut last: Option<(Token, usize)> = None;
+
+        for (t, span) in tokenize(input) {
+            let merge = last.is_some_and(|(lt, lp)| lt == t && lp + 1 == span.pos);
+            last = Some((t, span.pos));
+            match t {
+                Token::Right | Token::Left | Token::Increment | Token::Decrement if merge => {
+                    match &mut ops.last_mut().expect("merged with previous op").op {
+                        Op::Add(n) => { *n += if t == Token::Increment { 1 } else { -1 }; }
+                        Op::Move(n) => { *n += if t == Token::Right { 1 } else { -1 }; }
+                        _ => unreachable!(),
+                    }
+                }
+                Token::Right => { ops.push(Node::new(Op::Move(1), span)); }
+                Token::Left => { ops.push(Node::new(Op::Move(-1), span)); }
+                Token::Increment => { ops.push(Node::new(Op::Add(1), span)); }
+                Token::Decrement => { ops.push(Node::new(Op::Add(-1), span)); }
+                Token::Output => { ops.push(Node::new(Op::Out, span)); }
+                Token::Input => { ops.push(Node::new(Op::In, span)); }
+                Token::PrintNumber => { ops.push(Node::new(Op::PrintNum, span)); }
+                Token::LoopStart => {
+                    stack.push((std::mem::take(&mut ops), span));
+                }
+                Token::LoopEnd => {
+                    let (outer, start) = stack.pop()
+                        .ok_or_else(|| CompileError::UnmatchedClose(SourceError::new(input, span)))?;
+                    let body = std::mem::replace(&mut ops, outer);
+                    ops.push(Node::new(Op::Loop(body), start));
+                }
+            }
+        }
+        if let Some((_, start)) = stack.pop() {
+            return Err(CompileError::UnmatchedOpen(SourceError::new(input, start)));
+        }
+        let line_starts = std::iter::once(0)
+            .chain(input.chars().enumerate().filter(|(_, c)| *c == '\n').map(|(i, _)| i + 1))
+            .collect();
+        Ok(Program { ops, line_starts, ..Default::default() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// span of the unmatched bracket and the rendered error
+    fn unmatched(code: &str) -> (Span, String) {
+        let e = Program::parse(code).unwrap_err().with_file("a.b");
+        let span = match &e {
+            CompileError::UnmatchedOpen(s) | CompileError::UnmatchedClose(s) => { s.span }
+            e => { panic!("{e}") }
+        };
+        (span, e.to_string())
+    }
+
+    #[test]
+    fn loop_tree() {
+        let program = Program::parse("++ +>[-<\n+>]..").expect("valid program");
+        let ops: Vec<&Op> = program.ops.iter().map(|n| &n.op).collect();
+        // only adjacent tokens are merged
+        assert!(matches!(ops[..], [Op::Add(2), Op::Add(1), Op::Move(1), Op::Loop(_), Op::Out, Op::Out]), "{ops:?}");
+        let Op::Loop(body) = ops[3] else { unreachable!() };
+        let body: Vec<&Op> = body.iter().map(|n| &n.op).collect();
+        assert_eq!(body, [&Op::Add(-1), &Op::Move(-1), &Op::Add(1), &Op::Move(1)]);
+        // a loop is located at its `[`
+        assert_eq!((program.ops[3].span.line, program.ops[3].span.col), (1, 6));
+    }
+
+    #[test]
+    fn unmatched_open() {
+        let (span, text) = unmatched("+[\n [-]\n\t[>");
+        assert_eq!((span.line, span.col), (3, 2));
+        assert_eq!(text, "error: unmatched `[`, loop is never closed\n --> a.b:3:2\n  |\n3 | \t[>\n  | \t^");
+        // the innermost one is reported when several are open
+        let (span, _) = unmatched("[[[]");
+        assert_eq!((span.line, span.col), (1, 2));
+    }
+
+    #[test]
+    fn unmatched_close() {
+        let (span, text) = unmatched("+[-]]");
+        assert_eq!((span.line, span.col), (1, 5));
+        assert_eq!(text, "error: unmatched `]`, no loop to close\n --> a.b:1:5\n  |\n1 | +[-]]\n  |     ^");
+        let (span, text) = unmatched("+\r\n\r\n\r\n\r\n\r\n\r\n\r\n\r\n\r\nab ]");
+        assert_eq!((span.line, span.col), (10, 4));
+        assert!(text.ends_with("10 | ab ]\n   |    ^"), "{text}");
+    }
+
+    #[test]
+    fn line_starts() {
+        let code = "+\r\n>\n\n<[-]";
+        let program = Program::parse(code).expect("valid program");
+        assert_eq!(program.line_starts, [0, 3, 5, 6]);
+        // a span is found again from its char index the way generated code does it
+        for (_, span) in tokenize(code) {
+            let line = program.line_starts.partition_point(|s| *s <= span.pos);
+            assert_eq!((line, span.pos - program.line_starts[line - 1] + 1), (span.line, span.col));
+        }
+        assert_eq!(Program::parse("").expect("valid program").line_starts, [0]);
+        assert_eq!(Program::parse("+\n").expect("valid program").line_starts, [0, 2]);
+    }
+}
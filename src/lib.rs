@@ -0,0 +1,37 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+//! brainf*ck compiler library
+//!
+//! `tokenize` -> `ir::Program` -> `opt::optimize` -> `codegen::llvm::emit` (or another backend in `codegen`) or `interpreter::run`
+
+macro_rules! write {
+  ($dst:expr, $($arg:tt)*) => { _ = std::write!($dst, $($arg)*); }
+}
+
+pub mod error;
+pub mod token;
+pub mod ir;
+pub mod opt;
+pub mod settings;
+pub mod codegen;
+pub mod interpreter;
+
+pub use error::CompileError;
+pub use token::{Span, Token, tokenize};
+pub use ir::Program;
+pub use settings::{Eof, Settings, Tape, Terminal};
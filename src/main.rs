@@ -17,62 +17,81 @@
 
 
 
-#[allow(unused)]
-use std::fmt::Write as _;
-#[allow(unused)]
+use std::ffi::{OsStr, OsString};
 use std::io::Write as _;
-#[allow(unused)]
-use std::io::Read;
 use std::ops::Not;
-use std::path::{PathBuf};
+use std::path::{Path, PathBuf};
 #[allow(unused)]
 use clap::{arg, command, crate_authors, value_parser, ArgMatches};
 use clap::{Arg, ArgAction};
 use clap::builder::{PathBufValueParser};
+use brainfck::{CompileError, Eof, Program, Settings, Tape, Terminal};
+use brainfck::{codegen, interpreter, opt};
 
-macro_rules! write {
-  ($dst:expr, $($arg:tt)*) => { _ = std::write!($dst, $($arg)*); }
+#[derive(Debug)]
+struct SettingsArgs {
+    emit: Option<PathBuf>,
+    run_compiler: bool,
+    backend: Backend,
+    output: PathBuf,
+    print_stats: bool,
+    settings: Settings,
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum Token {
-    Right,
-    Left,
-    Increment,
-    Decrement,
-    Output,
-    Input,
-    LoopStart,
-    LoopEnd,
-    PrintNumber,
+/// what `compile` and `repl` emit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Llvm,
+    C,
+    X86_64Asm,
+    /// emitted file is the executable
+    Elf,
+    /// emitted file is not made into executable
+    Wat,
+    /// emitted module is not made into executable
+    Rust,
 }
 
-impl Token {
-    fn from_char(c: &char) -> Option<Self> {
-        Some(match c {
-            '>' => Token::Right,
-            '<' => Token::Left,
-            '+' => Token::Increment,
-            '-' => Token::Decrement,
-            '.' => Token::Output,
-            ',' => Token::Input,
-            '[' => Token::LoopStart,
-            ']' => Token::LoopEnd,
-            '!' => Token::PrintNumber,
-            _ => { return None; }
-        })
+impl Backend {
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            Backend::Llvm => Some("ll"),
+            Backend::C => Some("c"),
+            Backend::X86_64Asm => Some("s"),
+            Backend::Elf => None,
+            Backend::Wat => Some("wat"),
+            Backend::Rust => Some("rs"),
+        }
     }
-}
 
-const PREABLE: &'static str = include_str!("head.ll");
+    /// what is emitted, for messages
+    fn describe(self) -> &'static str {
+        match self {
+            Backend::Llvm => "LLVM IR",
+            Backend::C => "C source",
+            Backend::X86_64Asm => "assembly",
+            Backend::Elf => "ELF executable",
+            Backend::Wat => "WebAssembly text",
+            Backend::Rust => "Rust source",
+        }
+    }
 
-#[derive(Debug)]
-struct SettingsArgs {
-    llvm_emit: Option<PathBuf>,
-    run_clang: bool,
-    output: PathBuf,
-    cells_count: u16,
-    override_new_line_to_null: bool,
+    /// programs with their arguments which make executable `output` from `emitted` file, files in `temporary` are removed after
+    fn toolchain(self, emitted: &Path, output: &Path) -> (Vec<(&'static str, Vec<OsString>)>, Vec<PathBuf>) {
+        let args = |a: &[&OsStr]| a.iter().map(|s| s.to_os_string()).collect::<Vec<_>>();
+        match self {
+            Backend::Llvm => (vec![("clang", args(&[emitted.as_ref(), "-o".as_ref(), output.as_ref(), "-O3".as_ref()]))], vec![]),
+            Backend::C => (vec![("cc", args(&[emitted.as_ref(), "-o".as_ref(), output.as_ref(), "-O2".as_ref()]))], vec![]),
+            Backend::X86_64Asm => {
+                let object = emitted.with_extension("o");
+                (vec![
+                    ("as", args(&[emitted.as_ref(), "-o".as_ref(), object.as_ref()])),
+                    ("ld", args(&[object.as_ref(), "-o".as_ref(), output.as_ref()])),
+                ], vec![object])
+            }
+            Backend::Elf | Backend::Wat | Backend::Rust => (vec![], vec![]),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -84,10 +103,261 @@ struct CompileArgs {
 enum Args {
     Compile { code: String, ca: CompileArgs, sa: SettingsArgs },
     Repl { code: String, sa: SettingsArgs },
+    Run { code: String, source: PathBuf, settings: Settings },
+}
+
+/// arguments shared by commands which produce a program
+fn compile_args() -> Vec<Arg> {
+    vec![
+        Arg::new("ON")
+            .short('o')
+            .long("output")
+            .value_name("file")
+            .required(false)
+            .action(ArgAction::Set)
+            .default_value({
+                #[cfg(windows)]
+                { "out.exe" }
+                #[cfg(not(windows))]
+                { "out" }
+            })
+            .help("Specifies output filename"),
+        Arg::new("X")
+            .short('x')
+            .long("do-not-compile")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .default_value("false")
+            .help("Does not execute 'clang' ('cc' for C backend, 'as' and 'ld' for assembly) to compile emitted code to executable (with ELF backend does not copy emitted executable to output)")
+            .requires("LL"),
+        Arg::new("LL")
+            .short('e')
+            .long("emit-file")
+            .value_name("file")
+            .action(ArgAction::Set)
+            .required(false)
+            .value_parser(PathBufValueParser::new())
+            .help("Sets filename for emitted llvm IR, C source, assembly, ELF executable, WebAssembly text or Rust source"),
+        Arg::new("PE")
+            .long("partial-eval-steps")
+            .value_name("steps")
+            .action(ArgAction::Set)
+            .required(false)
+            .default_value("1000000")
+            .value_parser(value_parser!(usize))
+            .help("Executes at most this many steps of the program at compile time, until it reads input (0 disables it)"),
+        Arg::new("ST")
+            .long("stats")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .default_value("false")
+            .help("Prints how many bounds checks were removed"),
+        Arg::new("TG")
+            .long("target")
+            .value_name("triple")
+            .action(ArgAction::Set)
+            .required(false)
+            .help("Target triple of generated code, e.g. 'aarch64-apple-darwin' (default: host)"),
+        Arg::new("TM")
+            .long("terminal")
+            .value_name("mode")
+            .action(ArgAction::Set)
+            .required(false)
+            .default_value("raw")
+            .value_parser(["raw", "cooked", "untouched"])
+            .help("Terminal mode while the program runs, 'raw' reads keys without echo (stdin which is not a terminal is never changed)"),
+        Arg::new("BK")
+            .long("backend")
+            .value_name("backend")
+            .action(ArgAction::Set)
+            .required(false)
+            .default_value("llvm")
+            .value_parser(["llvm", "c", "x86_64-asm", "elf", "wat", "rust"])
+            .help("What is emitted, LLVM IR, C source, x86-64 assembly for Linux, x86-64 Linux executable written without any tools, WebAssembly text for WASI or Rust module with 'pub fn run' (the last two are never compiled further)"),
+    ]
+}
+
+/// arguments shared by every command which produces or runs a program
+fn settings_args() -> Vec<Arg> {
+    vec![
+        Arg::new("CC")
+            .short('c')
+            .long("cell-count")
+            .value_name("count")
+            .action(ArgAction::Set)
+            .required(false)
+            .default_value("30000")
+            .value_parser(value_parser!(u64).range(1..))
+            .help("Specifies how many cells should there be"),
+        Arg::new("CB")
+            .short('b')
+            .long("cell-bits")
+            .value_name("bits")
+            .action(ArgAction::Set)
+            .required(false)
+            .default_value("8")
+            .value_parser(["8", "16", "32", "64"])
+            .help("Specifies how many bits does a cell have"),
+        Arg::new("TP")
+            .long("tape")
+            .value_name("mode")
+            .action(ArgAction::Set)
+            .required(false)
+            .default_value("fixed")
+            .value_parser(["fixed", "growable", "wrap"])
+            .help("What happens when the pointer moves past either end of the tape, 'growable' doubles it and 'wrap' makes it a ring"),
+        Arg::new("MC")
+            .long("max-cells")
+            .value_name("count")
+            .action(ArgAction::Set)
+            .required(false)
+            .value_parser(value_parser!(u64).range(1..))
+            .help("Growable tape panics instead of growing past this many cells"),
+        Arg::new("ONL")
+            .short('n')
+            .long("override-new-line-as-null")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .default_value("false")
+            .help("Makes '\\n'(0) be interpreted by Input command(',') as null(0)"),
+        Arg::new("OF")
+            .long("check-overflow")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .default_value("false")
+            .help("Panics when a cell goes above its maximum or below 0"),
+        Arg::new("MS")
+            .long("max-steps")
+            .value_name("steps")
+            .action(ArgAction::Set)
+            .required(false)
+            .value_parser(value_parser!(u64))
+            .help("Stops the program after this many loop iterations, exit code is 2"),
+        Arg::new("TO")
+            .long("timeout-ms")
+            .value_name("ms")
+            .action(ArgAction::Set)
+            .required(false)
+            .value_parser(value_parser!(u64))
+            .help("Stops the program after running this many milliseconds, exit code is 2"),
+        Arg::new("DP")
+            .long("dump-tape-on-panic")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .default_value("false")
+            .help("Prints the pointer and cells around it to stderr when the program panics"),
+        Arg::new("DE")
+            .long("dump-tape-on-exit")
+            .action(ArgAction::SetTrue)
+            .required(false)
+            .default_value("false")
+            .help("Prints the pointer and cells around it to stderr when the program finishes"),
+        Arg::new("DW")
+            .long("dump-width")
+            .value_name("cells")
+            .action(ArgAction::Set)
+            .required(false)
+            .default_value("16")
+            .value_parser(value_parser!(u64))
+            .help("How many cells a tape dump prints"),
+        Arg::new("DF")
+            .long("dump-file")
+            .value_name("file")
+            .action(ArgAction::Set)
+            .required(false)
+            .value_parser(PathBufValueParser::new())
+            .help("Tape dump also writes every cell to this file"),
+        Arg::new("EOF")
+            .long("eof")
+            .value_name("mode")
+            .action(ArgAction::Set)
+            .required(false)
+            .default_value("minus-one")
+            .value_parser(["zero", "minus-one", "unchanged"])
+            .help("What Input command(',') stores at end of input"),
+    ]
+}
+
+fn settings_from(cmd: &ArgMatches) -> Result<Settings, CompileError> {
+    let cell_count = *cmd.get_one::<u64>("CC").unwrap();
+
+    let cell_bits = cmd.get_one::<String>("CB").unwrap().parse().expect("validated by clap");
+
+    let override_new_line_to_null = cmd.get_flag("ONL");
+
+    let tape = match cmd.get_one::<String>("TP").unwrap().as_str() {
+        "growable" => Tape::Growable,
+        "wrap" => Tape::Wrap,
+        _ => Tape::Fixed,
+    };
+    let max_cells = cmd.get_one::<u64>("MC").copied();
+    if let Some(max) = max_cells && tape == Tape::Growable && max < cell_count {
+        return Err(CompileError::Conflict(format!("--max-cells {max} is smaller than --cell-count {cell_count}")));
+    }
+
+    let eof = match cmd.get_one::<String>("EOF").unwrap().as_str() {
+        "zero" => Eof::Zero,
+        "unchanged" => Eof::Unchanged,
+        _ => Eof::MinusOne,
+    };
+
+    let check_overflow = cmd.get_flag("OF");
+
+    let max_steps = cmd.get_one::<u64>("MS").copied();
+    let timeout_ms = cmd.get_one::<u64>("TO").copied();
+
+    let dump_on_panic = cmd.get_flag("DP");
+    let dump_on_exit = cmd.get_flag("DE");
+    let dump_width = *cmd.get_one::<u64>("DW").unwrap();
+    let dump_file = cmd.get_one::<PathBuf>("DF").cloned();
+
+    Ok(Settings {
+        cell_count, cell_bits, override_new_line_to_null, eof, tape, max_cells, check_overflow, max_steps, timeout_ms,
+        dump_on_panic, dump_on_exit, dump_width, dump_file,
+        ..Default::default()
+    })
 }
 
-/// Err(failed?)
-fn process_args() -> Result<Args, bool> {
+/// values of `compile_args` together with `settings_from`
+fn settings_args_from(cmd: &ArgMatches) -> Result<SettingsArgs, CompileError> {
+    let backend = backend_from(cmd);
+
+    let emit = cmd.get_one::<PathBuf>("LL").map(|t| match backend.extension() {
+        Some(extension) => t.with_extension(extension),
+        None => t.clone(),
+    });
+
+    let run_compiler = cmd.get_flag("X").not();
+
+    let print_stats = cmd.get_flag("ST");
+
+    let output = PathBuf::from(cmd.get_one::<String>("ON").expect("expected to not fail due to default value being set"));
+
+    let mut settings = settings_from(cmd)?;
+    settings.partial_eval_steps = *cmd.get_one::<usize>("PE").unwrap();
+    settings.target = cmd.get_one::<String>("TG").cloned();
+    settings.terminal = match cmd.get_one::<String>("TM").unwrap().as_str() {
+        "cooked" => Terminal::Cooked,
+        "untouched" => Terminal::Untouched,
+        _ => Terminal::Raw,
+    };
+
+    Ok(SettingsArgs { emit, run_compiler, backend, output, print_stats, settings })
+}
+
+fn backend_from(cmd: &ArgMatches) -> Backend {
+    match cmd.get_one::<String>("BK").unwrap().as_str() {
+        "c" => Backend::C,
+        "x86_64-asm" => Backend::X86_64Asm,
+        "elf" => Backend::Elf,
+        "wat" => Backend::Wat,
+        "rust" => Backend::Rust,
+        _ => Backend::Llvm,
+    }
+}
+
+/// Ok(None) when there is nothing to do
+fn process_args() -> Result<Option<Args>, CompileError> {
     let mut cmd = clap::Command::new("brainfck compiler")
         .bin_name("bf")
         .version("0.1.0")
@@ -105,100 +375,21 @@ fn process_args() -> Result<Args, bool> {
                 .action(ArgAction::Set)
                 .value_name("source")
                 .help("Source file (file containing brainfck program)"))
-            .arg(Arg::new("ON")
-                .short('o')
-                .long("output")
-                .value_name("file")
-                .required(false)
-                .action(ArgAction::Set)
-                .default_value({
-                    #[cfg(windows)]
-                    { "out.exe" }
-                    #[cfg(not(windows))]
-                    { "out" }
-                })
-                .help("Specifies output filename"))
-            .arg(Arg::new("CC")
-                .short('c')
-                .long("cell-count")
-                .value_name("count")
-                .action(ArgAction::Set)
-                .required(false)
-                .default_value("30000")
-                .value_parser(value_parser!(u16))
-                .help("Specifies how many cells should there be"))
-            .arg(Arg::new("X")
-                .short('x')
-                .long("do-not-compile")
-                .action(ArgAction::SetTrue)
-                .required(false)
-                .default_value("false")
-                .help("Does not execute 'clang' to compile llvm IR to executable")
-                .requires("LL"))
-            .arg(Arg::new("LL")
-                .short('e')
-                .long("emit-file")
-                .value_name("file")
-                .action(ArgAction::Set)
-                .required(false)
-                .value_parser(PathBufValueParser::new())
-                .help("Sets filename for emitted llvm IR"))
-            .arg(Arg::new("ONL")
-                .short('n')
-                .long("override-new-line-as-null")
-                .action(ArgAction::SetTrue)
-                .required(false)
-                .default_value("false")
-                .help("Makes '\\n'(0) be interpreted by Input command(',') as null(0)")
-            )
+            .args(compile_args())
+            .args(settings_args())
         )
         .subcommand(command!("repl").about("Takes user input and compiles that as source")
-            .arg(Arg::new("ON")
-                .short('o')
-                .long("output")
-                .value_name("file")
-                .required(false)
-                .action(ArgAction::Set)
-                .default_value({
-                    #[cfg(windows)]
-                    { "out.exe" }
-                    #[cfg(not(windows))]
-                    { "out" }
-                })
-                .help("Specifies output filename"))
-            .arg(Arg::new("CC")
-                .short('c')
-                .long("cell-count")
-                .value_name("count")
-                .action(ArgAction::Set)
-                .required(false)
-                .default_value("30000")
-                .value_parser(value_parser!(u16))
-                .help("Specifies how many cells should there be"))
-            .arg(Arg::new("X")
-                .short('x')
-                .long("do-not-compile")
-                .action(ArgAction::SetTrue)
-                .required(false)
-                .default_value("false")
-                .help("Does not execute 'clang' to compile llvm IR to executable")
-                .requires("LL"))
-            .arg(Arg::new("LL")
-                .short('e')
-                .long("emit-file")
-                .value_name("file")
+            .args(compile_args())
+            .args(settings_args())
+        )
+        .subcommand(command!("run").about("Interprets a source file without compiling it")
+            .disable_version_flag(true)
+            .arg(Arg::new("FL")
+                .required(true)
                 .action(ArgAction::Set)
-                .required(false)
-                .value_parser(PathBufValueParser::new())
-                .help("Sets filename for emitted llvm IR"))
-            .arg(Arg::new("ONL")
-                .short('n')
-                .long("override-new-line-as-null")
-                .action(ArgAction::SetTrue)
-                .required(false)
-                .default_value("false")
-                .help("Makes '\\n'(0) be interpreted by Input command(',') as null(0)")
-            )
+                .value_name("source")
+                .help("Source file (file containing brainfck program)"))
+            .args(settings_args())
         )
         .subcommand(command!("about").about("Prints about this software and of its licence"));
 
@@ -224,57 +415,31 @@ fn process_args() -> Result<Args, bool> {
   You should have received a copy of the GNU General Public License
   along with this program.  If not, see <https://www.gnu.org/licenses/>.
         ");
-            Err(false)
+            Ok(None)
         }
         Some(("compile", cmd)) => {
             let name = cmd.get_one::<String>("FL").unwrap();
-            if !std::fs::exists(&name).unwrap_or(false) {
-                eprintln!("file '{name}' does not exist");
-                return Err(true);
-            }
             let source = PathBuf::from(name);
 
-            let llvm_emit = match cmd.get_one::<PathBuf>("LL") {
-                None => { None }
-                Some(t) => {
-                    let mut t = (*t).clone();
-                    t.set_extension("ll");
-                    Some(t)
-                },
-            };
+            let sa = settings_args_from(cmd)?;
 
-            let run_clang = cmd.get_flag("X").not();
+            let code = std::fs::read_to_string(&source)
+                .map_err(|error| CompileError::UnreadableFile { path: source.clone(), error })?;
 
-            let output = PathBuf::from(cmd.get_one::<String>("ON").expect("expected to not fail due to default value being set"));
-
-            let cells_count = *cmd.get_one::<u16>("CC").unwrap();
+            Ok(Some(Args::Compile{code, ca: CompileArgs { source }, sa}))
+        }
+        Some(("run", cmd)) => {
+            let source = PathBuf::from(cmd.get_one::<String>("FL").unwrap());
 
-            let override_new_line_to_null = cmd.get_flag("ONL");
+            let settings = settings_from(cmd)?;
 
-            let code = std::fs::read_to_string(name).unwrap();
+            let code = std::fs::read_to_string(&source)
+                .map_err(|error| CompileError::UnreadableFile { path: source.clone(), error })?;
 
-            Ok(Args::Compile{code, ca: CompileArgs { source }, sa: SettingsArgs {
-                llvm_emit,
-                run_clang,
-                output,
-                cells_count,
-                override_new_line_to_null,
-            }})
+            Ok(Some(Args::Run { code, source, settings }))
         }
         Some(("repl", cmd)) => {
-
-            let llvm_emit = match cmd.get_one::<PathBuf>("LL") {
-                None => { None }
-                Some(t) => Some((*t).clone()),
-            };
-
-            let run_clang = cmd.get_flag("X").not();
-
-            let output = PathBuf::from(cmd.get_one::<String>("ON").expect("expected to not fail due to default value being set"));
-
-            let cells_count = *cmd.get_one::<u16>("CC").unwrap();
-
-            let override_new_line_to_null = cmd.get_flag("ONL");
+            let sa = settings_args_from(cmd)?;
 
             println!("Welcome to REPL mode used to compile brainf*ck from user input and not from a file.");
             println!("Write 'exit' on empty line to proceed with compilation.");
@@ -289,17 +454,11 @@ fn process_args() -> Result<Args, bool> {
                 }
             }
 
-            Ok(Args::Repl { code: ot, sa: SettingsArgs {
-                llvm_emit,
-                run_clang,
-                output,
-                cells_count,
-                override_new_line_to_null,
-            } })
+            Ok(Some(Args::Repl { code: ot, sa }))
         }
         _ => {
             _ = cmd.print_help();
-            Err(true)
+            std::process::exit(1)
         }
     }
 }
@@ -307,253 +466,137 @@ fn process_args() -> Result<Args, bool> {
 fn main() {
     match run() {
         Ok(_) => { std::process::exit(0) }
-        Err(_) => { std::process::exit(1) }
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1)
+        }
     }
 }
 
-fn run() -> Result<(), ()> {
-
-    // (Token, count)
-    let mut tokens: Vec<(Token, usize, usize)> = vec![];
+fn run() -> Result<(), CompileError> {
 
-    let pa = match process_args() {
-        Ok(v) => { v }
-        Err(true) => { return Err(()); }
-        Err(false) => { return Ok(()) }
+    let pa = match process_args()? {
+        Some(v) => { v }
+        None => { return Ok(()) }
     };
 
     let (input, source, sa) = match pa {
-        Args::Compile { code, ca, sa} => { (code, match ca { CompileArgs { source} => Some(source)}, sa) }
+        Args::Compile { code, ca: CompileArgs { source }, sa} => { (code, Some(source), sa) }
         Args::Repl { code, sa} => { (code, None, sa) }
+        Args::Run { code, source, settings } => { return interpret(&code, &source, &settings) }
     };
 
-
-    let (output_file, llvm_ir_filename, emit_llvm_ir, override_enter_to_null, cell_count, clang_additional_arguments, run_clang) = match sa {
-        SettingsArgs { llvm_emit, run_clang, output, cells_count, override_new_line_to_null } => {
-            let emit = llvm_emit.is_some();
-            let ll = llvm_emit.unwrap_or(output.clone().with_extension(".ll"));
-            (output, ll, emit, override_new_line_to_null, cells_count, vec!["-O3"], run_clang)
+    let SettingsArgs { emit, run_compiler, backend, output: output_file, print_stats, settings } = sa;
+    // nothing to compile WebAssembly text or Rust module with, they are the result
+    let run_compiler = run_compiler && !matches!(backend, Backend::Wat | Backend::Rust);
+    let keep_emitted = emit.is_some() || matches!(backend, Backend::Elf | Backend::Wat | Backend::Rust);
+    let emitted_filename = emit.unwrap_or_else(|| match backend.extension() {
+        Some(extension) => output_file.with_extension(extension),
+        None => output_file.clone(),
+    });
+    let (toolchain, temporary) = backend.toolchain(&emitted_filename, &output_file);
+
+    if run_compiler {
+        for (tool, _) in &toolchain {
+            let has_tool = if cfg!(target_os = "windows") {
+                std::process::Command::new("cmd")
+                    .args(["/C", &format!("{tool} --version")])
+                    .output().map(|s| s.status.success()).unwrap_or(false)
+            } else {
+                std::process::Command::new(tool)
+                    .arg("--version")
+                    .output().map(|s| s.status.success()).unwrap_or(false)
+            };
+            if !has_tool {
+                return Err(CompileError::ToolNotFound(tool.to_string()));
+            }
         }
-    };
-
-    let has_clang = if cfg!(target_os = "windows") {
-        std::process::Command::new("cmd")
-            .args(["/C", "clang --version"])
-            .output().map(|s| s.status.success()).unwrap_or(false)
-    } else {
-        std::process::Command::new("clang")
-            .arg("--version")
-            .output().map(|s| s.status.success()).unwrap_or(false)
-    };
-
-    if !has_clang && run_clang {
-        eprintln!("requires 'clang'");
-        return Err(());
     }
 
-
-    let mut ch_iter = input.chars().enumerate().peekable();
-
-    while let Some((i, c)) = ch_iter.next() {
-        let t = Token::from_char(&c);
-        if t.is_none() { continue };
-        let t = t.unwrap();
-        let mut cnt: usize = 1;
-        while ch_iter.peek().is_some_and(|(_, n)| *n == c) {
-            cnt += 1;
-            ch_iter.next();
-        }
-        tokens.push((t, cnt, i));
+    match backend {
+        Backend::Llvm => { codegen::llvm::check(&settings)?; }
+        Backend::C => { codegen::c::check(&settings)?; }
+        Backend::X86_64Asm => { codegen::x86_64::check(&settings)?; }
+        Backend::Elf => { codegen::elf::check(&settings)?; }
+        Backend::Wat => { codegen::wat::check(&settings)?; }
+        Backend::Rust => { codegen::rust::check(&settings)?; }
     }
 
-    let mut f = String::new();
-    write!(f, "source_filename = \"{}\"\n", source.map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| "console".to_string()));
-    write_init(&mut f, cell_count);
-    
-    let mut brcks: Vec<(usize, usize)> = vec![];
-    let mut _consts: Vec<(String, String)> = vec![];
-
-    for (i, (token, cnt, pos)) in tokens.into_iter().enumerate() {
-        process_token(token, cnt, i, pos, &mut brcks, &mut _consts, override_enter_to_null, cell_count, &mut f);
+    let source_name = source.map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| "console".to_string());
+    let mut program = Program::parse(&input).map_err(|e| e.with_file(&source_name))?;
+    opt::optimize(&mut program, &settings);
+    opt::partial_eval(&mut program, &settings);
+    let stats = opt::eliminate_bounds_checks(&mut program, &settings);
+    if print_stats {
+        println!("bounds checks: {} of {} removed", stats.bounds_checks_removed, stats.bounds_checks);
     }
+    let f = match backend {
+        Backend::Llvm => { codegen::llvm::emit(&program, &source_name, &settings).into_bytes() }
+        Backend::C => { codegen::c::emit(&program, &source_name, &settings).into_bytes() }
+        Backend::X86_64Asm => { codegen::x86_64::emit(&program, &source_name, &settings).into_bytes() }
+        Backend::Elf => { codegen::elf::emit(&program, &source_name, &settings)? }
+        Backend::Wat => { codegen::wat::emit(&program, &source_name, &settings).into_bytes() }
+        Backend::Rust => { codegen::rust::emit(&program, &source_name, &settings).into_bytes() }
+    };
 
-    write!(f, "; ------- END ------ ;\n");
-    // write!(f, "  call void @putchar(i8 10)\n");
-    write!(f, "  br label %exit\n");
-    write!(f, "exit:\n");
-    write!(f, "  %exit_v = load i8, ptr %exit_code\n");
-    write!(f, "  ret i8 %exit_v\n");
-    write!(f, "}}\n");
-    for (c_name, c_val) in _consts {
-        write!(f, "@{c_name} = private constant [{} x i8] c\"{}\\00\"\n",c_val.len()+1, c_val.escape_debug());
+    std::fs::write(&emitted_filename, f)
+        .map_err(|error| CompileError::IrWrite { path: emitted_filename.clone(), error })?;
+    #[cfg(unix)]
+    if backend == Backend::Elf {
+        use std::os::unix::fs::PermissionsExt;
+        _ = std::fs::set_permissions(&emitted_filename, std::fs::Permissions::from_mode(0o755));
     }
 
-
-    _ = std::fs::write(&llvm_ir_filename, f);
-
-    if run_clang {
-        print!("invoking clang...");
-        std::io::stdout().flush();
-        let o = std::process::Command::new("clang").arg(&llvm_ir_filename).arg("-o").arg(&output_file).args(clang_additional_arguments).output().unwrap();
-        print!("\r");
-        if !o.status.success() {
-            eprintln!("clang failed with:\n{}", String::from_utf8(o.stderr).unwrap());
-            return Err(());
-        } else {
-            println!("compilation successful");
-            println!("written executable '{}'", output_file.display());
-            if emit_llvm_ir {
-                println!("emitted LLVM IR successfully to '{}'", llvm_ir_filename.display());
+    if run_compiler {
+        for (tool, args) in toolchain {
+            print!("invoking {tool}...");
+            _ = std::io::stdout().flush();
+            let o = std::process::Command::new(tool).args(args).output();
+            print!("\r");
+            let o = match o {
+                Ok(o) => { o }
+                // it could have disappeared since the check
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => { return Err(CompileError::ToolNotFound(tool.to_string())); }
+                Err(e) => { return Err(CompileError::Tool { name: tool.to_string(), stderr: e.to_string() }); }
+            };
+            if !o.status.success() {
+                return Err(CompileError::Tool { name: tool.to_string(), stderr: String::from_utf8_lossy(&o.stderr).to_string() });
             }
         }
+        for file in temporary {
+            _ = std::fs::remove_file(file);
+        }
+        // executable went to the emit file, output gets its copy
+        if backend == Backend::Elf && emitted_filename != output_file {
+            std::fs::copy(&emitted_filename, &output_file)
+                .map_err(|error| CompileError::IrWrite { path: output_file.clone(), error })?;
+        }
+        println!("compilation successful");
+        println!("written executable '{}'", output_file.display());
+        if keep_emitted {
+            println!("emitted {} successfully to '{}'", backend.describe(), emitted_filename.display());
+        }
     } else {
-        println!("emitted LLVM IR successfully to '{}'", llvm_ir_filename.display());
+        println!("emitted {} successfully to '{}'", backend.describe(), emitted_filename.display());
     }
 
-    if !emit_llvm_ir {
-        _ = std::fs::remove_file(&llvm_ir_filename);
+    if !keep_emitted {
+        _ = std::fs::remove_file(&emitted_filename);
     }
 
     Ok(())
 }
 
-fn write_init(f: &mut String, cell_count: u16) {
-    write!(f, "{}\n", PREABLE);
-    write!(f, "
-@bounds_r_panic_msg = private constant [{} x i8] c\"exceeded bounds check (larger than {cell_count})\\00\";
-@bounds_l_panic_msg = private constant [39 x i8] c\"exceeded bounds check (smaller than 0)\\00\";
-
-define i8 @code() {{
-init:
-  %exit_code = alloca i8
-  store i8 0, ptr %exit_code
-
-  %panic_msg = alloca i8*
-  %panic_pos = alloca i16
-  store i8* @none, ptr %panic_msg
-  %arr = alloca [{cell_count} x i8]
-  call void @llvm.memset.p0.i32(ptr %arr, i8 0, i32 {cell_count}, i1 0)
-  %pos = alloca i16
-  store i16 0, ptr %pos
-  br label %code
-panic:
-  %msg = load ptr, i8* %panic_msg
-  %p_pos = load i16, ptr %panic_pos
-  store i8 1, ptr %exit_code
-  call void @printf(ptr @panic_f, ptr %msg, i16 %p_pos)
-  br label %exit
-code:
-", "exceeded bounds check (larger than )".len() + cell_count.to_string().len() + 1);
-}
+fn interpret(code: &str, source: &Path, settings: &Settings) -> Result<(), CompileError> {
+    let mut program = Program::parse(code).map_err(|e| e.with_file(&source.to_string_lossy()))?;
+    opt::optimize(&mut program, settings);
 
-fn process_token(t: Token, count: usize, index: usize, file_pos: usize, brkcs: &mut Vec<(usize, usize)>, _consts: &mut Vec<(String, String)>, override_enter_to_null: bool, cell_count: u16, f: &mut String) {
-
-    // comment start
-    write!(f, "; ---- {index} | {:?} x {count} | at char {} ----\n", t, file_pos+1);
-    match t {
-        Token::Right => {
-            write!(f, "  %pos{index} = load i16, ptr %pos\n");
-            write!(f, "  %pos{index}n = add i16 %pos{index}, {count}\n");
-            // bounds check
-            write!(f, "  %rbound{index} = icmp uge i16 %pos{index}n, {cell_count}\n");
-            write!(f, "  br i1 %rbound{index}, label %bounds_panic{index}, label %continue{index}\n");
-            write!(f, "bounds_panic{index}:\n");
-            write!(f, "  store i16 {}, ptr %panic_pos\n", file_pos+1);
-            write!(f, "  store i8* @bounds_r_panic_msg, ptr %panic_msg\n");
-            write!(f, "  br label %panic\n");
-            write!(f, "continue{index}:\n");
-            // end
-            write!(f, "  store i16 %pos{index}n, ptr %pos\n");
-        }
-        Token::Left => {
-            write!(f, "  %pos{index} = load i16, ptr %pos\n");
-            write!(f, "  %pos{index}n = sub i16 %pos{index}, {count}\n");
-
-            // bounds check
-            write!(f, "  %lbound{index} = icmp ugt i16 %pos{index}n, %pos{index}");
-            write!(f, "  br i1 %lbound{index}, label %bounds_panic{index}, label %continue{index}\n");
-            write!(f, "bounds_panic{index}:\n");
-            write!(f, "  store i16 {}, ptr %panic_pos\n", file_pos+1);
-            write!(f, "  store i8* @bounds_l_panic_msg, ptr %panic_msg\n");
-            write!(f, "  br label %panic\n");
-            write!(f, "continue{index}:\n");
-            // end
-
-            write!(f, "  store i16 %pos{index}n, ptr %pos\n");
-        }
-        Token::Increment => {
-            write!(f, "  %pos{index} = load i16, ptr %pos\n");
-            write!(f, "  %t{index} = getelementptr i8, ptr %arr, i16 %pos{index}\n");
-            write!(f, "  %c{index} = load i8, ptr %t{index}\n");
-            write!(f, "  %c{index}n = add i8 %c{index}, {count}\n");
-            write!(f, "  store i8 %c{index}n, ptr %t{index}\n");
-        }
-        Token::Decrement => {
-            write!(f, "  %pos{index} = load i16, ptr %pos\n");
-            write!(f, "  %t{index} = getelementptr i8, ptr %arr, i16 %pos{index}\n");
-            write!(f, "  %c{index} = load i8, ptr %t{index}\n");
-            write!(f, "  %c{index}n = sub i8 %c{index}, {count}\n");
-            write!(f, "  store i8 %c{index}n, ptr %t{index}\n");
-        }
-        Token::Output => {
-            write!(f, "  %pos{index} = load i16, ptr %pos\n");
-            write!(f, "  %t{index} = getelementptr i8, ptr %arr, i16 %pos{index}\n");
-            write!(f, "  %c{index} = load i8, ptr %t{index}\n");
-            for _ in 0..count {
-                write!(f, "  call void @putchar(i8 %c{index})\n");
-            }
-        }
-        Token::Input => {
-            for _ in 0..count {
-                write!(f, "  %nc{index} = call i8 @getchar()\n");
-                write!(f, "  %pos{index} = load i16, ptr %pos\n");
-                write!(f, "  %t{index} = getelementptr i8, ptr %arr, i16 %pos{index}\n");
-
-                if override_enter_to_null {
-                    write!(f, "  %eof_is{index} = icmp eq i8 %nc{index}, 10\n");
-                    write!(f, "  %nsc{index} = select i1 %eof_is{index}, i8 0, i8 %nc{index}\n");
-                    write!(f, "  store i8 %nsc{index}, ptr %t{index}\n");
-                } else {
-                    write!(f, "  store i8 %nc{index}, ptr %t{index}\n");
-                }
-
-            }
-        }
-        Token::LoopStart => {
-            for k in 0..count {
-                write!(f, "  %pos{index}_{k} = load i16, ptr %pos\n");
-                write!(f, "  %t{index}_{k} = getelementptr i8, ptr %arr, i16 %pos{index}_{k}\n");
-                write!(f, "  %c{index}_{k} = load i8, ptr %t{index}_{k}\n");
-                write!(f, "  %cmp_rs{index}_{k} = icmp eq i8 %c{index}_{k}, 0\n");
-                write!(f, "  br i1 %cmp_rs{index}_{k}, label %skip{index}_{k}, label %loop{index}_{k}\n");
-                write!(f, "loop{index}_{k}:\n");
-                brkcs.push((index, k));
-            }
-        }
-        Token::LoopEnd => {
-            if brkcs.is_empty() { panic!("no matching loop bracket"); }
-            for k in 0..count {
-                let (n1, n2) = brkcs.pop().unwrap();
-                write!(f, "  %pos{index}_{k} = load i16, ptr %pos\n");
-                write!(f, "  %t{index}_{k} = getelementptr i8, ptr %arr, i16 %pos{index}_{k}\n");
-                write!(f, "  %c{index}_{k} = load i8, ptr %t{index}_{k}\n");
-                write!(f, "  %cmp_rs{index}_{k} = icmp ne i8 %c{index}_{k}, 0\n");
-                write!(f, "  br i1 %cmp_rs{index}_{k}, label %loop{n1}_{n2}, label %skip{n1}_{n2}\n");
-                write!(f, "skip{n1}_{n2}:\n");
-            }
-
-        }
-        Token::PrintNumber => {
-            write!(f, "  %pos{index} = load i16, ptr %pos\n");
-            write!(f, "  %t{index} = getelementptr i8, ptr %arr, i16 %pos{index}\n");
-            write!(f, "  %c{index} = load i8, ptr %t{index}\n");
-            for _ in 0..count {
-                write!(f, "  call void @printf(ptr @i_print, i8 %c{index})\n");
-            }
-        }
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    if let Err(p) = interpreter::run(&program, settings, &mut std::io::stdin().lock(), &mut stdout) {
+        let p = p.with_file(&source.to_string_lossy());
+        _ = writeln!(stdout, "\n{p}");
+        _ = stdout.flush();
+        std::process::exit(p.exit_code as i32);
     }
-
-    // end comment
-    write!(f, "; ------------\n\n");
+    Ok(())
 }
-
@@ -0,0 +1,503 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+//! optimisation passes over `ir::Program`
+
+use crate::interpreter::{State, Stop};
+use crate::ir::{Node, Op, Program};
+use crate::settings::{Settings, Tape};
+use crate::token::Span;
+
+/// with `Settings::check_overflow` only rewrites which keep every overflow are done
+pub fn optimize(program: &mut Program, settings: &Settings) {
+    program.ops = simplify_loops(canonicalize(std::mem::take(&mut program.ops), settings), settings);
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub bounds_checks: usize,
+    pub bounds_checks_removed: usize,
+}
+
+/// possible positions of the data pointer, `None` when unreachable
+type Range = Option<(i64, i64)>;
+
+/// marks ops which can never leave the tape as `in_bounds` by tracking range of the data pointer
+pub fn eliminate_bounds_checks(program: &mut Program, settings: &Settings) -> Stats {
+    let start = program.start as i64;
+    let limit = settings.tape_limit().unwrap_or(u64::MAX).min(i64::MAX as u64) as i64;
+    // cells below both the initial size and the limit are always there
+    let bounds = Bounds {
+        safe: limit.min(settings.cell_count.min(i64::MAX as u64) as i64),
+        end: limit,
+        wrap: settings.tape == Tape::Wrap,
+    };
+    ranges(&mut program.ops, Some((start, start)), bounds).1
+}
+
+/// `ranges` may skip checks of cells below `safe`, the pointer never reaches `end`
+#[derive(Clone, Copy)]
+struct Bounds {
+    safe: i64,
+    end: i64,
+    /// a move which leaves the tape lands anywhere on it
+    wrap: bool,
+}
+
+/// range after `ops` and counts of checks in them,
+/// marks are rewritten on every visit so that the last one, with the widest range, stays
+fn ranges(ops: &mut [Node], mut r: Range, bounds: Bounds) -> (Range, Stats) {
+    let mut stats = Stats::default();
+    for node in ops {
+        match &mut node.op {
+            &mut Op::Move(n) => {
+                let n = n as i64;
+                stats.bounds_checks += 1;
+                node.in_bounds = r.is_none_or(|(lo, hi)| lo + n >= 0 && hi.saturating_add(n) < bounds.safe);
+                r = if bounds.wrap && !node.in_bounds {
+                    r.map(|_| (0, bounds.end - 1))
+                } else {
+                    // when the check did not fire the pointer is on the tape
+                    r.map(|(lo, hi)| ((lo + n).max(0), hi.saturating_add(n).min(bounds.end - 1))).filter(|(lo, hi)| lo <= hi)
+                };
+            }
+            &mut Op::MulAdd(o, _) => {
+                let o = o as i64;
+                stats.bounds_checks += 1;
+                node.in_bounds = r.is_none_or(|(lo, hi)| lo + o >= 0 && hi.saturating_add(o) < bounds.safe);
+            }
+            &mut Op::Scan(n) => {
+                stats.bounds_checks += 1;
+                r = r.map(|(lo, hi)| match n {
+                    _ if bounds.wrap => (0, bounds.end - 1),
+                    1.. => (lo, bounds.end - 1),
+                    _ => (0, hi),
+                });
+            }
+            Op::Loop(body) => {
+                let mut entry = r;
+                let inner = loop {
+                    let (out, inner) = ranges(body, entry, bounds);
+                    let next = join(entry, out);
+                    // counts of the last pass, which saw the widest range
+                    if next == entry {
+                        break inner;
+                    }
+                    // widening, a side which grows goes straight to the end of the tape
+                    entry = match (entry, next) {
+                        (Some((lo, hi)), Some((nlo, nhi))) => Some((
+                            if nlo < lo { 0 } else { lo },
+                            if nhi > hi { bounds.end - 1 } else { hi },
+                        )),
+                        _ => next,
+                    };
+                };
+                stats.bounds_checks += inner.bounds_checks;
+                stats.bounds_checks_removed += inner.bounds_checks_removed;
+                r = entry;
+                continue;
+            }
+            _ => { continue; }
+        }
+        if node.in_bounds {
+            stats.bounds_checks_removed += 1;
+        }
+    }
+    (r, stats)
+}
+
+fn join(a: Range, b: Range) -> Range {
+    match (a, b) {
+        (Some((alo, ahi)), Some((blo, bhi))) => Some((alo.min(blo), ahi.max(bhi))),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// runs the longest prefix of top level ops which does not read input at compile time,
+/// its output and resulting tape become the initial state of the program,
+/// an op which panics or does not finish within `settings.partial_eval_steps` is left for runtime
+pub fn partial_eval(program: &mut Program, settings: &Settings) {
+    // iterations run at compile time would not count against the limit
+    if settings.partial_eval_steps == 0 || settings.max_steps.is_some() {
+        return;
+    }
+    let mut state = State::new(program, settings, std::io::empty(), Vec::new());
+    state.steps = settings.partial_eval_steps;
+
+    let mut done = 0;
+    for node in &program.ops {
+        if reads_input(node) {
+            break;
+        }
+        let (tape, pos, out_len) = (state.tape.clone(), state.pos, state.output.len());
+        match state.exec(std::slice::from_ref(node)) {
+            Ok(()) => { done += 1; }
+            Err(Stop::Panic(_) | Stop::OutOfSteps) => {
+                state.tape = tape;
+                state.pos = pos;
+                state.output.truncate(out_len);
+                break;
+            }
+        }
+    }
+
+    program.ops.drain(..done);
+    let used = state.tape.iter().rposition(|c| *c != 0).map(|i| i + 1).unwrap_or(0);
+    state.tape.truncate(used);
+    program.tape = state.tape;
+    program.start = state.pos;
+    program.output = state.output;
+}
+
+fn reads_input(node: &Node) -> bool {
+    match &node.op {
+        Op::In => true,
+        Op::Loop(body) => body.iter().any(reads_input),
+        _ => false,
+    }
+}
+
+/// folds neighbouring adds and moves into one, those with no effect are dropped,
+/// merged op keeps span of the first one,
+/// so a bounds panic in the middle of a fold (`<>` at cell 0) is gone
+fn canonicalize(ops: Vec<Node>, settings: &Settings) -> Vec<Node> {
+    let mut out: Vec<Node> = Vec::with_capacity(ops.len());
+    for node in ops {
+        let node = match node.op {
+            Op::Loop(body) => Node::new(Op::Loop(canonicalize(body, settings)), node.span),
+            _ => node,
+        };
+        let last = out.last_mut().map(|l| &mut l.op);
+        match (last, &node.op) {
+            // `+-` can overflow on its own
+            (Some(Op::Add(a)), Op::Add(b)) if !settings.check_overflow || a.signum() == b.signum() => { *a = a.wrapping_add(*b); }
+            (Some(Op::Move(a)), Op::Move(b)) => { *a += *b; }
+            (_, Op::Add(0) | Op::Move(0)) => {}
+            _ => {
+                out.push(node);
+                continue;
+            }
+        }
+        if matches!(out.last().map(|l| &l.op), Some(Op::Add(0) | Op::Move(0))) {
+            out.pop();
+        }
+    }
+    out
+}
+
+/// replaces clear loops (`[-]`), multiply / copy loops (`[->++>+<<]`) and scan loops (`[>>]`)
+fn simplify_loops(ops: Vec<Node>, settings: &Settings) -> Vec<Node> {
+    let mut out = Vec::with_capacity(ops.len());
+    for node in ops {
+        match node.op {
+            Op::Loop(body) => {
+                let body = simplify_loops(body, settings);
+                if let [Node { op: Op::Move(n), span, .. }] = body[..] {
+                    // bounds panic is reported at the move, same as with the loop
+                    out.push(Node::new(Op::Scan(n), span));
+                    continue;
+                }
+                // only `[-]` never overflows
+                let keeps_overflow = !settings.check_overflow || matches!(body[..], [Node { op: Op::Add(-1), .. }]);
+                match mul_loop(&body).filter(|_| keeps_overflow) {
+                    Some(adds) => {
+                        for (offset, factor, span) in adds {
+                            out.push(Node::new(Op::MulAdd(offset, factor), span));
+                        }
+                        out.push(Node::new(Op::SetZero, node.span));
+                    }
+                    None => { out.push(Node::new(Op::Loop(body), node.span)); }
+                }
+            }
+            op => { out.push(Node::new(op, node.span)); }
+        }
+    }
+    out
+}
+
+/// (offset, factor, span) for every cell a balanced loop with single decrement (or increment) changes,
+/// span is of the first move reaching the offset so that bounds panics point where they would without this
+fn mul_loop(body: &[Node]) -> Option<Vec<(isize, i32, Span)>> {
+    let mut offset: isize = 0;
+    // (offset reached by move, its span)
+    let mut moves: Vec<(isize, Span)> = vec![];
+    let mut adds: Vec<(isize, i32)> = vec![];
+    for Node { op, span, .. } in body {
+        match *op {
+            Op::Move(n) => {
+                offset += n;
+                moves.push((offset, *span));
+            }
+            Op::Add(n) => {
+                match adds.iter_mut().find(|(o, _)| *o == offset) {
+                    Some(a) => { a.1 = a.1.wrapping_add(n); }
+                    None => { adds.push((offset, n)); }
+                }
+            }
+            _ => { return None; }
+        }
+    }
+    if offset != 0 {
+        return None;
+    }
+    // loop running -c times (mod 2^bits) is the same as running c times with negated factors
+    let sign = match adds.iter().find(|(o, _)| *o == 0).map(|a| a.1) {
+        Some(-1) => 1,
+        Some(1) => -1,
+        _ => { return None; }
+    };
+    adds.retain(|(o, f)| *o != 0 && *f != 0);
+
+    // bounds are checked only for changed cells, furthest visited ones have to be among them
+    let hi = moves.iter().map(|m| m.0).max().unwrap_or(0);
+    let lo = moves.iter().map(|m| m.0).min().unwrap_or(0);
+    if [lo, hi].iter().any(|e| *e != 0 && !adds.iter().any(|a| a.0 == *e)) {
+        return None;
+    }
+
+    let mut r: Vec<(isize, i32, Span)> = adds.into_iter().map(|(o, f)| {
+        let span = moves.iter()
+            .find(|(m, _)| if o > 0 { *m >= o } else { *m <= o })
+            .expect("offset was reached by a move").1;
+        (o, f.wrapping_mul(sign), span)
+    }).collect();
+    r.sort_by_key(|a| a.2.pos);
+    Some(r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{self, Panic};
+
+    fn settings(cell_count: u64) -> Settings {
+        Settings { cell_count, ..Default::default() }
+    }
+
+    fn optimized(code: &str, settings: &Settings) -> Program {
+        let mut program = Program::parse(code).expect("valid program");
+        optimize(&mut program, settings);
+        program
+    }
+
+    /// ops without spans
+    fn ops(program: &Program) -> Vec<Op> {
+        fn strip(nodes: &[Node]) -> Vec<Op> {
+            nodes.iter().map(|n| match &n.op {
+                Op::Loop(body) => Op::Loop(strip(body).into_iter().map(|op| Node::new(op, Span::default())).collect()),
+                op => op.clone(),
+            }).collect()
+        }
+        strip(&program.ops)
+    }
+
+    fn run(program: &Program, settings: &Settings) -> (Vec<u8>, Result<(), Panic>) {
+        let mut output = vec![];
+        let r = interpreter::run(program, settings, &mut &b""[..], &mut output);
+        (output, r)
+    }
+
+    /// optimized program ends the same way as the parsed one
+    fn same_as_unoptimized(code: &str, settings: &Settings) -> Result<(), Panic> {
+        let (out, r) = run(&optimized(code, settings), settings);
+        assert_eq!((out, r.clone()), run(&Program::parse(code).expect("valid program"), settings), "{code}");
+        r
+    }
+
+    #[test]
+    fn clear_loops() {
+        assert_eq!(ops(&optimized("[-]", &settings(4))), [Op::SetZero]);
+        assert_eq!(ops(&optimized("[+]", &settings(4))), [Op::SetZero]);
+    }
+
+    #[test]
+    fn mul_loops() {
+        assert_eq!(ops(&optimized("[->++>+<<]", &settings(4))), [Op::MulAdd(1, 2), Op::MulAdd(2, 1), Op::SetZero]);
+        // running -c times is the same as c times with negated factors
+        assert_eq!(ops(&optimized("[<+++>+]", &settings(4))), [Op::MulAdd(-1, -3), Op::SetZero]);
+        assert_eq!(same_as_unoptimized("+++[->++>+<<]>!>!", &settings(4)), Ok(()));
+    }
+
+    #[test]
+    fn unbalanced_loops_stay() {
+        for code in ["[->+]", "[->>+<]", "[-->+<]", "[->+<.]", "[>+<]"] {
+            assert!(matches!(ops(&optimized(code, &settings(4)))[..], [Op::Loop(_)]), "{code}");
+        }
+        // `[-+]` cancels into `[]`, which never ends
+        assert!(matches!(ops(&optimized("[-+]", &settings(4)))[..], [Op::Loop(ref b)] if b.is_empty()));
+    }
+
+    #[test]
+    fn overflow_check_keeps_mul_loops() {
+        let settings = Settings { check_overflow: true, ..settings(4) };
+        assert_eq!(ops(&optimized("[-]", &settings)), [Op::SetZero]);
+        assert!(matches!(ops(&optimized("[+]", &settings))[..], [Op::Loop(_)]));
+        assert!(matches!(ops(&optimized("[->+<]", &settings))[..], [Op::Loop(_)]));
+    }
+
+    #[test]
+    fn scans() {
+        assert_eq!(ops(&optimized("[>]", &settings(4))), [Op::Scan(1)]);
+        assert_eq!(ops(&optimized("[<<]", &settings(4))), [Op::Scan(-2)]);
+        assert!(same_as_unoptimized("+>+>+[<]", &settings(4)).is_err());
+        assert!(same_as_unoptimized("<<<+[>]", &settings(4)).is_err());
+        assert_eq!(same_as_unoptimized("+>+>>+<<<[>]>!", &settings(4)), Ok(()));
+    }
+
+    #[test]
+    fn net_zero_moves_fold_away() {
+        let settings = settings(2);
+        assert_eq!(ops(&optimized("<>+", &settings)), [Op::Add(1)]);
+        assert!(ops(&optimized(">><<+--+", &settings)).is_empty());
+        assert_eq!(run(&optimized("<>!", &settings), &settings), (b"0\n".to_vec(), Ok(())));
+        let (_, r) = run(&Program::parse("<>!").expect("valid program"), &settings);
+        assert_eq!(r.unwrap_err().message, crate::interpreter::BOUNDS_L_PANIC_MSG);
+    }
+
+    #[test]
+    fn mul_loops_at_tape_edges() {
+        let settings = settings(2);
+        // nonzero cell on the last one moves past it, at the `>` inside the loop
+        let p = same_as_unoptimized("+>+[->+<]", &settings).unwrap_err();
+        assert_eq!(p.span.col, 6);
+        // zero cell never enters the loop, so nothing is checked
+        assert_eq!(same_as_unoptimized("+>[->+<]", &settings), Ok(()));
+        let p = same_as_unoptimized("+[-<+>]", &settings).unwrap_err();
+        assert_eq!((p.message.as_str(), p.span.col), (crate::interpreter::BOUNDS_L_PANIC_MSG, 4));
+        // first target is on the tape, the second is not
+        let p = same_as_unoptimized("+[->+>+<<]", &settings).unwrap_err();
+        assert_eq!(p.span.col, 6);
+    }
+
+    fn evaluated(code: &str, settings: &Settings) -> Program {
+        let mut program = Program::parse(code).expect("valid program");
+        partial_eval(&mut program, settings);
+        program
+    }
+
+    #[test]
+    fn partial_eval_runs_prefix() {
+        let p = evaluated("++>+++<.>!", &settings(4));
+        assert!(p.ops.is_empty());
+        assert_eq!((p.output, p.tape, p.start), (b"\x023\n".to_vec(), vec![2, 3], 1));
+    }
+
+    #[test]
+    fn partial_eval_stops_at_input() {
+        let p = evaluated("+>+[-],+.", &settings(4));
+        assert_eq!(ops(&p), [Op::In, Op::Add(1), Op::Out]);
+        assert_eq!((p.tape, p.start), (vec![1], 1));
+    }
+
+    #[test]
+    fn partial_eval_rolls_back_panic() {
+        // the loop prints twice before leaving the tape
+        let p = evaluated("+.[.>+]", &settings(2));
+        assert!(matches!(ops(&p)[..], [Op::Loop(_)]));
+        assert_eq!((p.output.clone(), p.tape.clone(), p.start), (vec![1], vec![1], 0));
+        let (out, r) = run(&p, &settings(2));
+        assert_eq!((out, r.unwrap_err().span.col), (vec![1, 1, 1], 5));
+    }
+
+    #[test]
+    fn partial_eval_rolls_back_out_of_steps() {
+        let settings = Settings { partial_eval_steps: 5, ..settings(4) };
+        let p = evaluated("+>+.[.]", &settings);
+        assert!(matches!(ops(&p)[..], [Op::Loop(_)]));
+        assert_eq!((p.output, p.tape, p.start), (vec![1], vec![1, 1], 1));
+    }
+
+    #[test]
+    fn partial_eval_skipped() {
+        for settings in [
+            Settings { max_steps: Some(100), ..settings(4) },
+            Settings { partial_eval_steps: 0, ..settings(4) },
+        ] {
+            let p = evaluated("+++.", &settings);
+            assert_eq!(ops(&p), [Op::Add(3), Op::Out]);
+            assert!(p.output.is_empty() && p.tape.is_empty() && p.start == 0);
+        }
+    }
+
+    fn checked(code: &str, settings: &Settings) -> (Program, Stats) {
+        let mut program = optimized(code, settings);
+        let stats = eliminate_bounds_checks(&mut program, settings);
+        (program, stats)
+    }
+
+    /// `in_bounds` of every op with a check, in program order
+    fn marks(nodes: &[Node]) -> Vec<bool> {
+        nodes.iter().flat_map(|n| match &n.op {
+            Op::Loop(body) => marks(body),
+            Op::Move(_) | Op::MulAdd(..) | Op::Scan(_) => vec![n.in_bounds],
+            _ => vec![],
+        }).collect()
+    }
+
+    fn stats(bounds_checks: usize, bounds_checks_removed: usize) -> Stats {
+        Stats { bounds_checks, bounds_checks_removed }
+    }
+
+    #[test]
+    fn bounds_checks_straight() {
+        let (p, s) = checked(">+<", &settings(4));
+        assert_eq!((marks(&p.ops), s), (vec![true, true], stats(2, 2)));
+        let (p, s) = checked("<", &settings(4));
+        assert_eq!((marks(&p.ops), s), (vec![false], stats(1, 0)));
+        let (p, s) = checked(">>>>", &settings(4));
+        assert_eq!((marks(&p.ops), s), (vec![false], stats(1, 0)));
+    }
+
+    #[test]
+    fn bounds_checks_in_loops() {
+        // the pointer moves further on every iteration
+        let (mut p, s) = checked("+[>+]", &settings(4));
+        assert_eq!((marks(&p.ops), s), (vec![false], stats(1, 0)));
+        assert_eq!(eliminate_bounds_checks(&mut p, &settings(4)), s);
+        // returns where it started
+        let (p, s) = checked("++[>++[>+<-]<-]", &settings(4));
+        assert_eq!((marks(&p.ops), s), (vec![true, true, true], stats(3, 3)));
+        let (p, s) = checked("+[>+<-]>[<+>>+<-]", &settings(4));
+        assert_eq!((marks(&p.ops), s), (vec![true; 4], stats(4, 4)));
+    }
+
+    #[test]
+    fn bounds_checks_after_scans() {
+        // scan itself is always checked, the pointer may end anywhere on its side
+        let (p, s) = checked("[>]<", &settings(4));
+        assert_eq!((marks(&p.ops), s), (vec![false, false], stats(2, 0)));
+        let (p, s) = checked("[<]>", &settings(4));
+        assert_eq!((marks(&p.ops), s), (vec![false, true], stats(2, 1)));
+    }
+
+    #[test]
+    fn bounds_checks_wrap() {
+        let fixed = settings(3);
+        let wrap = Settings { tape: Tape::Wrap, ..settings(3) };
+        // after leaving a fixed tape nothing runs
+        let (p, s) = checked(">>>.<", &fixed);
+        assert_eq!((marks(&p.ops), s), (vec![false, true], stats(2, 1)));
+        // on a wrapping one the pointer can be anywhere
+        let (p, s) = checked(">>>.<", &wrap);
+        assert_eq!((marks(&p.ops), s), (vec![false, false], stats(2, 0)));
+        let (p, s) = checked(">>>>", &wrap);
+        assert_eq!((marks(&p.ops), s), (vec![false], stats(1, 0)));
+        let (p, s) = checked(">>", &wrap);
+        assert_eq!((marks(&p.ops), s), (vec![true], stats(1, 1)));
+    }
+}
@@ -0,0 +1,137 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+use std::path::PathBuf;
+use crate::ir::Program;
+
+/// what happens when the data pointer leaves the tape
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tape {
+    /// panics
+    #[default]
+    Fixed,
+    /// tape doubles its size, up to `Settings::max_cells`
+    Growable,
+    /// pointer is taken modulo `Settings::cell_count`
+    Wrap,
+}
+
+/// what ',' stores at end of input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Eof {
+    Zero,
+    /// all bits set
+    #[default]
+    MinusOne,
+    /// cell keeps its value
+    Unchanged,
+}
+
+/// what the compiled program does with the terminal it reads from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Terminal {
+    /// input is read without waiting for a new line and is not echoed
+    #[default]
+    Raw,
+    /// input is line buffered and echoed
+    Cooked,
+    /// terminal is left as it is
+    Untouched,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// cells of the tape, initial size of a growable one
+    pub cell_count: u64,
+    /// 8, 16, 32 or 64
+    pub cell_bits: u32,
+    /// makes ',' store 0 instead of '\n'
+    pub override_new_line_to_null: bool,
+    pub eof: Eof,
+    /// makes `+` and `-` panic instead of wrapping
+    pub check_overflow: bool,
+    /// loop iterations after which the program is stopped
+    pub max_steps: Option<u64>,
+    /// milliseconds after which the program is stopped
+    pub timeout_ms: Option<u64>,
+    /// prints data pointer and cells around it to stderr when the program panics
+    pub dump_on_panic: bool,
+    /// prints data pointer and cells around it to stderr when the program finishes
+    pub dump_on_exit: bool,
+    /// how many cells a tape dump prints
+    pub dump_width: u64,
+    /// a tape dump also writes every cell to this file
+    pub dump_file: Option<PathBuf>,
+    /// budget of `opt::partial_eval`, 0 disables it
+    pub partial_eval_steps: usize,
+    pub tape: Tape,
+    /// ceiling of a growable tape, unlimited when `None`
+    pub max_cells: Option<u64>,
+    /// target triple of generated code, the host when `None`
+    pub target: Option<String>,
+    pub terminal: Terminal,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            cell_count: 30000,
+            cell_bits: 8,
+            override_new_line_to_null: false,
+            eof: Eof::MinusOne,
+            check_overflow: false,
+            max_steps: None,
+            timeout_ms: None,
+            dump_on_panic: false,
+            dump_on_exit: false,
+            dump_width: 16,
+            dump_file: None,
+            partial_eval_steps: 1_000_000,
+            tape: Tape::Fixed,
+            max_cells: None,
+            target: None,
+            terminal: Terminal::Raw,
+        }
+    }
+}
+
+impl Settings {
+    /// mask of bits a cell holds
+    pub fn cell_mask(&self) -> u64 {
+        u64::MAX >> (64 - self.cell_bits)
+    }
+
+    /// cells the data pointer can reach, `None` when unlimited
+    pub fn tape_limit(&self) -> Option<u64> {
+        match self.tape {
+            Tape::Fixed | Tape::Wrap => Some(self.cell_count),
+            Tape::Growable => self.max_cells,
+        }
+    }
+
+    /// cells allocated when `program` starts, never more than `max_cells`,
+    /// partial evaluation may have left the pointer past the initial size of a growable tape
+    pub fn initial_cells(&self, program: &Program) -> u64 {
+        match self.tape {
+            Tape::Growable => {
+                let cells = self.cell_count.max(program.tape.len() as u64).max(program.start as u64 + 1);
+                self.max_cells.map_or(cells, |max| cells.min(max))
+            }
+            Tape::Fixed | Tape::Wrap => self.cell_count,
+        }
+    }
+}
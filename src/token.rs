@@ -0,0 +1,73 @@
+//  Brainfck compiler
+//  Copyright (C) 2025  František Slivko <slivko.frantisek@gmail.com>
+//
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Right,
+    Left,
+    Increment,
+    Decrement,
+    Output,
+    Input,
+    LoopStart,
+    LoopEnd,
+    PrintNumber,
+}
+
+impl Token {
+    pub fn from_char(c: &char) -> Option<Self> {
+        Some(match c {
+            '>' => Token::Right,
+            '<' => Token::Left,
+            '+' => Token::Increment,
+            '-' => Token::Decrement,
+            '.' => Token::Output,
+            ',' => Token::Input,
+            '[' => Token::LoopStart,
+            ']' => Token::LoopEnd,
+            '!' => Token::PrintNumber,
+            _ => { return None; }
+        })
+    }
+}
+
+/// position in source, `line` and `col` start at 1
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// char index
+    pub pos: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+/// everything that is not a command is skipped
+pub fn tokenize(input: &str) -> Vec<(Token, Span)> {
+    let mut tokens = vec![];
+    let (mut line, mut col) = (1, 1);
+    for (pos, c) in input.chars().enumerate() {
+        if let Some(t) = Token::from_char(&c) {
+            tokens.push((t, Span { pos, line, col }));
+        }
+        if c == '\n' {
+            line += 1;
+            col = 1;
+        } else {
+            col += 1;
+        }
+    }
+    tokens
+}